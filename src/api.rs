@@ -0,0 +1,253 @@
+//! The JSON API, for tools which want to know what the nerds found without scraping the HTML.
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+
+use crate::{
+    cache, filters,
+    input::{self, InputError},
+    nerds::{Category, Class, NumberContext, NumberInfo},
+    AppState,
+};
+
+/// How much the `accept` header says the client wants `media_type`, as its quality
+/// and how specifically it was named: 2 for the type itself, 1 for `type/*` and 0 for `*/*`.
+/// The most specific media range naming the type decides, as in RFC 9110.
+fn preference(accept: &str, media_type: &str) -> Option<(f32, u8)> {
+    let (kind, _) = media_type.split_once('/')?;
+    accept
+        .split(',')
+        .filter_map(|range| {
+            let mut params = range.split(';');
+            let range = params.next()?.trim().to_ascii_lowercase();
+            let specificity = if range == media_type {
+                2
+            } else if range.strip_suffix("/*") == Some(kind) {
+                1
+            } else if range == "*/*" {
+                0
+            } else {
+                return None;
+            };
+            let quality = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("q"))
+                .map_or(Some(1.0), |(_, q)| q.trim().parse::<f32>().ok())?;
+            Some((specificity, quality))
+        })
+        .max_by_key(|&(specificity, _)| specificity)
+        .map(|(specificity, quality)| (quality, specificity))
+}
+
+/// Whether the client asked for JSON rather than a web page.
+/// Ties go to the type named more specifically, and otherwise to the web page.
+pub fn wants_json(headers: &HeaderMap) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+    else {
+        return false;
+    };
+    match preference(accept, "application/json") {
+        Some((quality, _)) if quality <= 0.0 => false,
+        Some(json) => preference(accept, "text/html").is_none_or(|html| json > html),
+        None => false,
+    }
+}
+
+/// Text written in the markup understood by the `mathfmt` filter, in every form a client may want.
+#[derive(Debug, Serialize)]
+struct Markup {
+    source: String,
+    html: String,
+    text: String,
+    /// Every number linked to with `(#k)`, in decimal.
+    numbers: Vec<String>,
+}
+
+impl Markup {
+    fn new(source: &str) -> Self {
+        let (text, numbers) = filters::plain_text(source);
+        Self {
+            source: source.to_owned(),
+            // Note: This can't fail, mathfmt always returns Ok.
+            html: filters::mathfmt(source, &filters::LinkStyle::Absolute).unwrap(),
+            text,
+            numbers,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ApiFact {
+    category: Category,
+    #[serde(flatten)]
+    fact: Markup,
+}
+
+#[derive(Debug, Serialize)]
+struct ApiForm {
+    description: String,
+    #[serde(flatten)]
+    form: Markup,
+}
+
+/// Everything the nerds found about a number.
+#[derive(Debug, Serialize)]
+pub struct ApiInfo {
+    /// The number in decimal.
+    number: String,
+    facts: Vec<ApiFact>,
+    forms: Vec<ApiForm>,
+    classes: Vec<Class>,
+    timed_out: Vec<&'static str>,
+    failed: Vec<&'static str>,
+    skipped: Vec<&'static str>,
+}
+
+impl ApiInfo {
+    pub fn new(ctx: &NumberContext, info: &NumberInfo) -> Self {
+        let facts = info
+            .facts
+            .iter()
+            .flat_map(|(category, facts)| {
+                facts.iter().map(|fact| ApiFact {
+                    category: *category,
+                    fact: Markup::new(fact),
+                })
+            })
+            .collect();
+        let forms = info
+            .forms
+            .iter()
+            .map(|(description, form)| ApiForm {
+                description: description.clone(),
+                form: Markup::new(form),
+            })
+            .collect();
+        Self {
+            number: ctx.digits().to_owned(),
+            facts,
+            forms,
+            classes: info.classes.clone(),
+            timed_out: info.timed_out.clone(),
+            failed: info.failed.clone(),
+            skipped: info.skipped.clone(),
+        }
+    }
+}
+
+impl IntoResponse for ApiInfo {
+    fn into_response(self) -> Response {
+        Json(self).into_response()
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorBody {
+    code: &'static str,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+impl IntoResponse for InputError {
+    /// Renders the error as JSON, e.g. `{"error": {"code": "negative", "message": "..."}}`.
+    fn into_response(self) -> Response {
+        let body = ErrorResponse {
+            error: ErrorBody {
+                code: self.code(),
+                message: self.to_string(),
+            },
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+pub async fn handle_int(
+    State(state): State<Arc<AppState>>,
+    Path(param): Path<String>,
+) -> Result<ApiInfo, InputError> {
+    let (n, _) = input::parse(&param, state.config.max_digits)?;
+    let ctx = state.config.number_context(n);
+    let info = cache::finished(crate::analyse(&state, &ctx)).await;
+    Ok(ApiInfo::new(&ctx, &info))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rug::Integer;
+    use serde_json::json;
+
+    #[test]
+    fn negotiates_json() {
+        let wants = |accept: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::ACCEPT, accept.parse().unwrap());
+            wants_json(&headers)
+        };
+        assert!(!wants_json(&HeaderMap::new()));
+        assert!(wants("application/json"));
+        assert!(wants("application/json, text/plain, */*"));
+        assert!(wants("text/html;q=0.5, application/json"));
+        assert!(wants("application/*, text/html;q=0.9"));
+        assert!(!wants("*/*"));
+        assert!(!wants("text/html, application/json;q=0.1"));
+        assert!(!wants(
+            "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"
+        ));
+        assert!(!wants("application/json;q=0"));
+        assert!(!wants("application/json, text/html"));
+        assert!(wants("application/json, text/*"));
+    }
+
+    #[test]
+    fn facts_are_structured() {
+        let ctx = NumberContext::new(Integer::from(8191));
+        let info = NumberInfo {
+            facts: vec![(
+                Category::Primality,
+                vec!["Is a Mersenne prime: (#2)(^(#13))-(#1)".to_owned()],
+            )],
+            forms: vec![("Binary".to_owned(), "1111111111111".to_owned())],
+            classes: vec![Class::Odd, Class::Prime, Class::MersennePrime],
+            ..Default::default()
+        };
+        let json = serde_json::to_value(ApiInfo::new(&ctx, &info)).unwrap();
+        assert_eq!(
+            json,
+            json!({
+                "number": "8191",
+                "facts": [{
+                    "category": "primality",
+                    "source": "Is a Mersenne prime: (#2)(^(#13))-(#1)",
+                    "html": "Is a Mersenne prime: <a href=\"/2\">2</a><sup><a href=\"/13\">13</a></sup>-<a href=\"/1\">1</a>",
+                    "text": "Is a Mersenne prime: 2^13-1",
+                    "numbers": ["2", "13", "1"],
+                }],
+                "forms": [{
+                    "description": "Binary",
+                    "source": "1111111111111",
+                    "html": "1111111111111",
+                    "text": "1111111111111",
+                    "numbers": [],
+                }],
+                "classes": ["odd", "prime", "mersenne-prime"],
+                "timed_out": [],
+                "failed": [],
+                "skipped": [],
+            })
+        );
+    }
+}
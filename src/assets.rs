@@ -0,0 +1,135 @@
+//! Static files like the stylesheet, which are built into the binary.
+//!
+//! Files in an override directory are served instead of the built-in ones with the same name,
+//! so they can be edited without rebuilding.
+use std::{
+    borrow::Cow,
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use axum::{
+    extract::{Path as UrlPath, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use rust_embed::RustEmbed;
+
+use crate::AppState;
+
+#[derive(RustEmbed)]
+#[folder = "assets/"]
+struct Embedded;
+
+pub struct Assets {
+    dir: Option<PathBuf>,
+}
+
+/// Whether `name` is a plain file name, which can't point outside the assets.
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_'))
+}
+
+fn content_type(name: &str) -> &'static str {
+    match Path::new(name).extension().and_then(|ext| ext.to_str()) {
+        Some("css") => "text/css; charset=utf-8",
+        Some("js") => "text/javascript; charset=utf-8",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("ico") => "image/x-icon",
+        Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+impl Assets {
+    /// The built-in assets, overridden by those in `dir` if there is one.
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self { dir }
+    }
+
+    /// The contents of the asset called `name`, if there is one.
+    pub async fn get(&self, name: &str) -> Option<Cow<'static, [u8]>> {
+        if !is_plain_name(name) {
+            return None;
+        }
+        if let Some(dir) = &self.dir {
+            if let Ok(data) = tokio::fs::read(dir.join(name)).await {
+                return Some(Cow::Owned(data));
+            }
+        }
+        Embedded::get(name).map(|file| file.data)
+    }
+
+    /// The names of every asset.
+    pub fn names(&self) -> BTreeSet<String> {
+        let mut names: BTreeSet<_> = Embedded::iter().map(String::from).collect();
+        if let Some(Ok(entries)) = self.dir.as_ref().map(std::fs::read_dir) {
+            names.extend(
+                entries
+                    .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                    .filter(|name| is_plain_name(name)),
+            );
+        }
+        names
+    }
+}
+
+pub async fn handle_asset(
+    State(state): State<Arc<AppState>>,
+    UrlPath(name): UrlPath<String>,
+) -> Response {
+    match state.assets.get(&name).await {
+        Some(data) => ([(header::CONTENT_TYPE, content_type(&name))], data).into_response(),
+        None => (StatusCode::NOT_FOUND, "Error: no such file").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_plain() {
+        assert!(is_plain_name("style.css"));
+        assert!(is_plain_name("natwiki-logo_2.svg"));
+        for name in [
+            "",
+            "..",
+            ".hidden",
+            "../Cargo.toml",
+            "a/b.css",
+            "a\\b.css",
+            "%2e%2e",
+        ] {
+            assert!(!is_plain_name(name), "{name}");
+        }
+    }
+
+    #[test]
+    fn overrides_built_in_assets() {
+        crate::test_harness!(|| {
+            let builtin = Assets::new(None);
+            assert!(builtin.names().contains("style.css"));
+            assert!(builtin.get("style.css").await.is_some());
+            assert!(builtin.get("missing.css").await.is_none());
+
+            let dir = std::env::temp_dir().join(format!("natwiki-assets-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("style.css"), "body {}").unwrap();
+            std::fs::write(dir.join("extra.js"), "").unwrap();
+            let overridden = Assets::new(Some(dir.clone()));
+            let style = overridden.get("style.css").await;
+            let names = overridden.names();
+            std::fs::remove_dir_all(&dir).unwrap();
+
+            assert_eq!(style.as_deref(), Some(&b"body {}"[..]));
+            assert!(names.contains("style.css") && names.contains("extra.js"));
+        });
+    }
+}
@@ -0,0 +1,358 @@
+//! Remembering what the nerds found, so popular numbers aren't analysed on every request.
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use rug::Integer;
+use tokio::sync::watch;
+
+use crate::nerds::{Fact, NumberContext, NumberInfo};
+
+/// Every entry costs this much on top of its digits, for the facts about it and the bookkeeping.
+const ENTRY_OVERHEAD: usize = 256;
+/// How long analyses which timed out are kept, which is plenty for a page that was sent
+/// before the analysis finished to ask for the rest of its facts.
+const KEEP_INCOMPLETE: Duration = Duration::from_secs(10);
+
+/// How far an analysis has come.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// Everything the nerds said so far, in the order it was heard.
+    /// Empty for analyses which were finished before anyone asked.
+    pub facts: Vec<Fact>,
+    /// The finished analysis, once there is one.
+    pub info: Option<Arc<NumberInfo>>,
+}
+
+/// Lets an analysis tell everyone waiting for it what it has found so far.
+#[derive(Clone)]
+pub struct Reporter(Arc<watch::Sender<Progress>>);
+
+impl Reporter {
+    pub fn report(&self, fact: &Fact) {
+        self.0
+            .send_modify(|progress| progress.facts.push(fact.clone()));
+    }
+}
+
+/// Waits for the analysis to finish.
+pub async fn finished(mut progress: watch::Receiver<Progress>) -> Arc<NumberInfo> {
+    // Note: This can't fail, the analysis always finishes before it lets go of the sender.
+    let progress = progress
+        .wait_for(|progress| progress.info.is_some())
+        .await
+        .unwrap();
+    progress.info.clone().unwrap()
+}
+
+/// A bounded cache of finished analyses, which evicts the least recently used numbers first.
+///
+/// The size of an entry is measured by the length of the number in decimal,
+/// since that is what most of its forms and facts grow with.
+/// Concurrent requests for the same number share one analysis.
+pub struct InfoCache {
+    capacity: usize,
+    keep_incomplete: Duration,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<Integer, Entry>,
+    /// The cached numbers by when they were last used, oldest first.
+    recency: BTreeMap<u64, Integer>,
+    clock: u64,
+    size: usize,
+    /// Analyses which haven't finished yet.
+    in_flight: HashMap<Integer, watch::Receiver<Progress>>,
+    /// Analyses which finished incomplete, along with when to forget them.
+    /// Their facts are kept in the order they were heard, which pages streaming them rely on.
+    incomplete: HashMap<Integer, (Instant, watch::Receiver<Progress>)>,
+}
+
+struct Entry {
+    info: Arc<NumberInfo>,
+    size: usize,
+    last_used: u64,
+}
+
+impl Inner {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn get(&mut self, n: &Integer) -> Option<Arc<NumberInfo>> {
+        let now = self.tick();
+        let entry = self.entries.get_mut(n)?;
+        let n = self.recency.remove(&entry.last_used)?;
+        entry.last_used = now;
+        self.recency.insert(now, n);
+        Some(entry.info.clone())
+    }
+
+    fn insert(&mut self, n: Integer, info: Arc<NumberInfo>, size: usize, capacity: usize) {
+        if size > capacity || self.entries.contains_key(&n) {
+            return;
+        }
+        while self.size + size > capacity {
+            let Some((_, oldest)) = self.recency.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                self.size -= entry.size;
+            }
+        }
+        let now = self.tick();
+        self.recency.insert(now, n.clone());
+        self.entries.insert(
+            n,
+            Entry {
+                info,
+                size,
+                last_used: now,
+            },
+        );
+        self.size += size;
+    }
+}
+
+impl InfoCache {
+    /// A cache holding numbers of up to `capacity` decimal digits in total.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            keep_incomplete: KEEP_INCOMPLETE,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// The analysis of the context's number, which is started in the background with `compute`
+    /// unless it is cached or already running.
+    ///
+    /// The analysis keeps running if nobody is waiting for it anymore, so it can be cached.
+    /// Only complete analyses are cached. Incomplete ones are kept just long enough for
+    /// pages to stream the rest of their facts, so numbers which timed out get another chance later.
+    pub fn analyse<F, Fut>(
+        self: &Arc<Self>,
+        ctx: &NumberContext,
+        compute: F,
+    ) -> watch::Receiver<Progress>
+    where
+        F: FnOnce(Reporter) -> Fut,
+        Fut: Future<Output = NumberInfo> + Send + 'static,
+    {
+        let n = ctx.n();
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(info) = inner.get(n) {
+            let (_, progress) = watch::channel(Progress {
+                facts: Vec::new(),
+                info: Some(info),
+            });
+            return progress;
+        }
+        if let Some(progress) = inner.in_flight.get(n) {
+            return progress.clone();
+        }
+        let now = Instant::now();
+        inner.incomplete.retain(|_, (until, _)| *until > now);
+        if let Some((_, progress)) = inner.incomplete.get(n) {
+            return progress.clone();
+        }
+
+        let (tx, progress) = watch::channel(Progress::default());
+        let tx = Arc::new(tx);
+        inner.in_flight.insert(n.clone(), progress.clone());
+        let analysis = compute(Reporter(tx.clone()));
+        let cache = self.clone();
+        let n = n.clone();
+        let size = ctx.digits().len() + ENTRY_OVERHEAD;
+        tokio::spawn(async move {
+            let info = Arc::new(analysis.await);
+            {
+                let mut inner = cache.inner.lock().unwrap();
+                // Note: This can't fail, only this task removes the number.
+                let progress = inner.in_flight.remove(&n).unwrap();
+                if info.is_complete() {
+                    inner.insert(n, info.clone(), size, cache.capacity);
+                } else {
+                    let until = Instant::now() + cache.keep_incomplete;
+                    inner.incomplete.insert(n, (until, progress));
+                }
+            }
+            tx.send_modify(|progress| progress.info = Some(info));
+        });
+        progress
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use crate::{
+        math::Budget,
+        nerds::{self, Category},
+    };
+
+    /// Analyses `n` with a fake computation, counting how often it runs.
+    async fn lookup(cache: &Arc<InfoCache>, n: u32, runs: &Arc<AtomicUsize>) -> Arc<NumberInfo> {
+        let ctx = NumberContext::new(Integer::from(n));
+        let runs = runs.clone();
+        finished(cache.analyse(&ctx, |_| async move {
+            runs.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            NumberInfo::default()
+        }))
+        .await
+    }
+
+    #[test]
+    fn shares_analyses() {
+        crate::test_harness!(|| {
+            let cache = Arc::new(InfoCache::new(10_000));
+            let runs = Arc::default();
+            let (a, b) = tokio::join!(lookup(&cache, 37, &runs), lookup(&cache, 37, &runs));
+            assert!(Arc::ptr_eq(&a, &b));
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+            let c = lookup(&cache, 37, &runs).await;
+            assert!(Arc::ptr_eq(&a, &c));
+            assert_eq!(runs.load(Ordering::SeqCst), 1);
+        });
+    }
+
+    #[test]
+    fn reports_progress() {
+        crate::test_harness!(|| {
+            let cache = Arc::new(InfoCache::new(10_000));
+            let ctx = NumberContext::new(Integer::from(12));
+            let fact = Fact::basic(Category::Divisibility, 3, "Is an even number.");
+            let (go, wait) = tokio::sync::oneshot::channel::<()>();
+            let mut progress = cache.analyse(&ctx, |reporter| {
+                let fact = fact.clone();
+                async move {
+                    reporter.report(&fact);
+                    wait.await.unwrap();
+                    NumberInfo::default()
+                }
+            });
+
+            let heard = progress
+                .wait_for(|progress| !progress.facts.is_empty())
+                .await
+                .unwrap()
+                .clone();
+            assert_eq!(heard.facts, vec![fact]);
+            assert!(heard.info.is_none());
+
+            go.send(()).unwrap();
+            finished(progress).await;
+        });
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        crate::test_harness!(|| {
+            let cache = Arc::new(InfoCache::new(3 * (2 + ENTRY_OVERHEAD)));
+            let runs = Arc::default();
+            for n in [10, 11, 12, 10, 13] {
+                lookup(&cache, n, &runs).await;
+            }
+            assert_eq!(runs.load(Ordering::SeqCst), 4);
+
+            // 11 was used least recently, so it made room for 13.
+            lookup(&cache, 10, &runs).await;
+            lookup(&cache, 12, &runs).await;
+            assert_eq!(runs.load(Ordering::SeqCst), 4);
+            lookup(&cache, 11, &runs).await;
+            assert_eq!(runs.load(Ordering::SeqCst), 5);
+        });
+    }
+
+    #[test]
+    fn large_numbers_take_more_room() {
+        crate::test_harness!(|| {
+            let cache = Arc::new(InfoCache::new(2 * (2 + ENTRY_OVERHEAD)));
+            let runs = Arc::default();
+            lookup(&cache, 10, &runs).await;
+            lookup(&cache, 11, &runs).await;
+            // Too large to fit next to either of them.
+            lookup(&cache, 1_000_000, &runs).await;
+            lookup(&cache, 10, &runs).await;
+            lookup(&cache, 11, &runs).await;
+            assert_eq!(runs.load(Ordering::SeqCst), 5);
+        });
+    }
+
+    #[test]
+    fn truncated_walks_are_not_cached() {
+        crate::test_harness!(|| {
+            let mut registry = nerds::Registry::default();
+            let names: Vec<_> = registry.iter().map(|(nerd, _)| nerd.name()).collect();
+            for name in names {
+                registry.set_enabled(name, name == "aliquot");
+            }
+            let cache = Arc::new(InfoCache {
+                keep_incomplete: Duration::ZERO,
+                ..InfoCache::new(10_000)
+            });
+            let runs = Arc::new(AtomicUsize::new(0));
+            for _ in 0..2 {
+                // Out of time before the aliquot sequence of 12 gets anywhere.
+                let budget = Budget::unlimited();
+                budget.cancel();
+                let ctx = Arc::new(NumberContext::new(Integer::from(12)).with_budget(budget));
+                let (registry, runs) = (registry.clone(), runs.clone());
+                let info = finished(cache.analyse(&ctx.clone(), |_| async move {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                    nerds::ask_nerds(&registry, ctx, None).await
+                }))
+                .await;
+                assert_eq!(info.timed_out, vec!["aliquot"]);
+                assert!(!info.is_complete());
+            }
+            assert_eq!(runs.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn incomplete_results_are_kept_briefly() {
+        crate::test_harness!(|| {
+            let ctx = NumberContext::new(Integer::from(12));
+            let fact = Fact::basic(Category::Divisibility, 3, "Is an even number.");
+            let timed_out = |reporter: Reporter| {
+                reporter.report(&fact);
+                async {
+                    NumberInfo {
+                        timed_out: vec!["slowpoke"],
+                        ..Default::default()
+                    }
+                }
+            };
+
+            // Long enough for the rest of the page's facts to be streamed.
+            let cache = Arc::new(InfoCache::new(10_000));
+            let first = finished(cache.analyse(&ctx, timed_out)).await;
+            let again = cache.analyse(&ctx, timed_out);
+            assert_eq!(again.borrow().facts, vec![fact.clone()]);
+            assert!(Arc::ptr_eq(&first, &finished(again).await));
+
+            let cache = Arc::new(InfoCache {
+                keep_incomplete: Duration::ZERO,
+                ..InfoCache::new(10_000)
+            });
+            let first = finished(cache.analyse(&ctx, timed_out)).await;
+            let second = finished(cache.analyse(&ctx, timed_out)).await;
+            assert!(!Arc::ptr_eq(&first, &second));
+        });
+    }
+}
@@ -0,0 +1,461 @@
+//! Command line arguments, and showing what the nerds found in a terminal.
+use std::{fmt::Write, ops::RangeInclusive, path::PathBuf, process::ExitCode, time::Duration};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use rug::Integer;
+
+use crate::{
+    api::ApiInfo,
+    config::{Config, ConfigError},
+    filters::terminal_text,
+    input,
+    nerds::{self, NumberContext, NumberInfo},
+    store::Store,
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "A wiki about natural numbers.")]
+pub struct Args {
+    /// Runs the web server if omitted.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub settings: Settings,
+}
+
+/// Settings which take precedence over the config file. Each can also be set in the environment.
+#[derive(Debug, Default, clap::Args)]
+pub struct Settings {
+    /// The TOML file to read the settings from.
+    #[arg(long, global = true, env = "NATWIKI_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// The address to listen on [default: 0.0.0.0:3000].
+    #[arg(long, global = true, env = "NATWIKI_BIND")]
+    bind: Option<String>,
+    /// The most decimal digits a number may have.
+    #[arg(long, global = true, env = "NATWIKI_MAX_DIGITS")]
+    max_digits: Option<u32>,
+    /// How many seconds the nerds may spend on a single number.
+    #[arg(long, global = true, env = "NATWIKI_TIME_BUDGET", value_parser = parse_secs)]
+    time_budget: Option<Duration>,
+    /// How many seconds to search for prime factors.
+    #[arg(long, global = true, env = "NATWIKI_FACTOR_BUDGET", value_parser = parse_secs)]
+    factor_budget: Option<Duration>,
+    /// How many seconds `precompute` lets the nerds spend on each number.
+    #[arg(long, global = true, env = "NATWIKI_PRECOMPUTE_TIME_BUDGET", value_parser = parse_secs)]
+    precompute_time_budget: Option<Duration>,
+    /// How many seconds `precompute` searches for prime factors.
+    #[arg(long, global = true, env = "NATWIKI_PRECOMPUTE_FACTOR_BUDGET", value_parser = parse_secs)]
+    precompute_factor_budget: Option<Duration>,
+    /// How many seconds to wait for the nerds before sending an incomplete page.
+    #[arg(long, global = true, env = "NATWIKI_FIRST_PAINT", value_parser = parse_secs)]
+    first_paint: Option<Duration>,
+    /// Number of Miller-Rabin rounds used by the primality test.
+    #[arg(long, global = true, env = "NATWIKI_PRIME_ROUNDS")]
+    prime_rounds: Option<u32>,
+    /// The chance that `/random` adds another digit.
+    #[arg(long, global = true, env = "NATWIKI_RANDOM_DIGIT_CHANCE")]
+    random_digit_chance: Option<f64>,
+    /// How many decimal digits worth of numbers to remember the facts about.
+    #[arg(long, global = true, env = "NATWIKI_CACHE_CAPACITY")]
+    cache_capacity: Option<usize>,
+    /// The SQLite database to store expensive facts in.
+    #[arg(long, global = true, env = "NATWIKI_STORE")]
+    store: Option<PathBuf>,
+    /// How many seconds a nerd must have taken for its facts to be stored.
+    #[arg(long, global = true, env = "NATWIKI_STORE_AFTER", value_parser = parse_secs)]
+    store_after: Option<Duration>,
+    /// A directory of manual pages to use instead of the built-in ones.
+    #[arg(long, global = true, env = "NATWIKI_MANUAL")]
+    manual_dir: Option<PathBuf>,
+    /// A directory of assets to use instead of the built-in ones.
+    #[arg(long, global = true, env = "NATWIKI_ASSETS")]
+    assets_dir: Option<PathBuf>,
+    /// Whether to show the effective config at `/admin/config`.
+    #[arg(long, global = true, env = "NATWIKI_ADMIN_PAGE")]
+    admin_page: Option<bool>,
+    /// Nerds which should not be asked, separated by commas.
+    #[arg(
+        long,
+        global = true,
+        env = "NATWIKI_DISABLED_NERDS",
+        value_delimiter = ','
+    )]
+    disable_nerds: Vec<String>,
+    /// Nerds which should be asked even if the config file disables them, separated by commas.
+    #[arg(
+        long,
+        global = true,
+        env = "NATWIKI_ENABLED_NERDS",
+        value_delimiter = ','
+    )]
+    enable_nerds: Vec<String>,
+}
+
+/// Replaces `setting` with `value`, if there is one.
+fn set<T: Clone>(setting: &mut T, value: &Option<T>) {
+    if let Some(value) = value {
+        *setting = value.clone();
+    }
+}
+
+impl Settings {
+    /// The settings from the config file, if there is one, with these applied on top.
+    pub fn config(&self) -> Result<Config, ConfigError> {
+        let mut config = match &self.config {
+            Some(path) => Config::read(path)?,
+            None => Config::default(),
+        };
+        set(&mut config.bind, &self.bind);
+        set(&mut config.max_digits, &self.max_digits);
+        set(&mut config.time_budget, &self.time_budget);
+        set(&mut config.factor_budget, &self.factor_budget);
+        set(
+            &mut config.precompute_time_budget,
+            &self.precompute_time_budget,
+        );
+        set(
+            &mut config.precompute_factor_budget,
+            &self.precompute_factor_budget,
+        );
+        set(&mut config.first_paint, &self.first_paint);
+        set(&mut config.prime_rounds, &self.prime_rounds);
+        set(&mut config.random_digit_chance, &self.random_digit_chance);
+        set(&mut config.cache_capacity, &self.cache_capacity);
+        set(&mut config.store_after, &self.store_after);
+        set(&mut config.admin_page, &self.admin_page);
+        if self.store.is_some() {
+            config.store.clone_from(&self.store);
+        }
+        if self.manual_dir.is_some() {
+            config.manual_dir.clone_from(&self.manual_dir);
+        }
+        if self.assets_dir.is_some() {
+            config.assets_dir.clone_from(&self.assets_dir);
+        }
+        for (names, enabled) in [(&self.disable_nerds, false), (&self.enable_nerds, true)] {
+            for name in names
+                .iter()
+                .map(|name| name.trim())
+                .filter(|name| !name.is_empty())
+            {
+                config.nerds.insert(name.to_owned(), enabled);
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+fn parse_secs(s: &str) -> Result<Duration, String> {
+    let secs = s
+        .trim()
+        .parse::<f64>()
+        .map_err(|err| format!("{s:?} is not a number of seconds: {err}"))?;
+    Duration::try_from_secs_f64(secs).map_err(|err| format!("{s:?} is not a duration: {err}"))
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Runs the web server.
+    Serve,
+    /// Prints what the nerds know about a number.
+    Show {
+        /// The number, in decimal.
+        n: String,
+        #[arg(long, value_enum, default_value_t)]
+        format: Format,
+    },
+    /// Renders a static copy of the site for a range of numbers.
+    Build {
+        /// The numbers to render, e.g. `0..=10000` or `0..10000`.
+        #[arg(long, value_parser = parse_u64_range)]
+        range: RangeInclusive<u64>,
+        /// The directory to write the site to.
+        #[arg(long)]
+        out: PathBuf,
+        /// The URL the site will be hosted at, like `https://example.com/wiki/`.
+        /// The sitemap is only written if this is given, since it needs absolute URLs.
+        #[arg(long, value_parser = parse_base_url)]
+        base_url: Option<String>,
+    },
+    /// Fills the fact store with what the nerds know about a range of numbers.
+    /// Picks up where it left off if it was interrupted.
+    Precompute {
+        /// The numbers to ask about, e.g. `0..=10000` or `0..10000`.
+        #[arg(long, value_parser = parse_range)]
+        range: RangeInclusive<Integer>,
+    },
+}
+
+fn parse_base_url(s: &str) -> Result<String, String> {
+    if s.starts_with("https://") || s.starts_with("http://") {
+        Ok(s.to_owned())
+    } else {
+        Err(format!(
+            "{s:?} is not an absolute URL starting with https://"
+        ))
+    }
+}
+
+fn parse_range(s: &str) -> Result<RangeInclusive<Integer>, String> {
+    let parse = |n: &str| match n.trim().parse::<Integer>() {
+        Ok(n) if n >= 0 => Ok(n),
+        Ok(_) => Err(format!("{n:?} is negative")),
+        Err(err) => Err(format!("{n:?} is not a valid bound: {err}")),
+    };
+    if let Some((start, end)) = s.split_once("..=") {
+        Ok(parse(start)?..=parse(end)?)
+    } else if let Some((start, end)) = s.split_once("..") {
+        let end = parse(end)?;
+        if end == 0 {
+            return Err(format!("{s:?} is empty"));
+        }
+        Ok(parse(start)?..=end - 1_u8)
+    } else {
+        Err(format!("{s:?} is not a range like 0..=100"))
+    }
+}
+
+fn parse_u64_range(s: &str) -> Result<RangeInclusive<u64>, String> {
+    let (start, end) = parse_range(s)?.into_inner();
+    match (start.to_u64(), end.to_u64()) {
+        (Some(start), Some(end)) => Ok(start..=end),
+        _ => Err(format!("{s:?} goes past {}", u64::MAX)),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Plain text, with Unicode superscripts.
+    #[default]
+    Text,
+    Markdown,
+    /// The same JSON as is served by the API.
+    Json,
+}
+
+/// Asks the nerds about `n` and prints what they found to stdout.
+pub async fn show(
+    registry: &nerds::Registry,
+    store: Option<&Store>,
+    config: &Config,
+    n: &str,
+    format: Format,
+) -> ExitCode {
+    let n = match input::parse(n, config.max_digits) {
+        Ok((n, _)) => n,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let ctx = config.number_context(n);
+    let info = nerds::ask_nerds(registry, ctx.clone(), store).await;
+    print!("{}", render(format, &ctx, &info));
+    ExitCode::SUCCESS
+}
+
+fn render(format: Format, ctx: &NumberContext, info: &NumberInfo) -> String {
+    match format {
+        Format::Text => render_text(ctx, info),
+        Format::Markdown => render_markdown(ctx, info),
+        Format::Json => {
+            // Note: This can't fail, ApiInfo has no maps with non-string keys.
+            let mut json = serde_json::to_string_pretty(&ApiInfo::new(ctx, info)).unwrap();
+            json.push('\n');
+            json
+        }
+    }
+}
+
+/// Notes about the nerds which didn't finish or weren't asked, if there were any.
+fn incomplete_notes(info: &NumberInfo) -> Vec<String> {
+    let mut notes = Vec::new();
+    if !info.timed_out.is_empty() {
+        notes.push(format!(
+            "These analyses ran out of time: {}.",
+            info.timed_out.join(", ")
+        ));
+    }
+    if !info.failed.is_empty() {
+        notes.push(format!(
+            "These analyses failed: {}.",
+            info.failed.join(", ")
+        ));
+    }
+    if !info.skipped.is_empty() {
+        notes.push(format!(
+            "These analyses were skipped because the number is too large: {}.",
+            info.skipped.join(", ")
+        ));
+    }
+    notes
+}
+
+// Note: Writing to a String can't fail, so the results of writeln! are ignored below.
+
+fn render_text(ctx: &NumberContext, info: &NumberInfo) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "{}", ctx.digits());
+    if !info.forms.is_empty() {
+        let _ = writeln!(out, "\nAlternative forms");
+        for (desc, form) in &info.forms {
+            let _ = writeln!(out, "  {desc}: {}", terminal_text(form));
+        }
+    }
+    for (category, facts) in &info.facts {
+        let _ = writeln!(out, "\n{}", category.heading());
+        for fact in facts {
+            let _ = writeln!(out, "  - {}", terminal_text(fact));
+        }
+    }
+    for note in incomplete_notes(info) {
+        let _ = writeln!(out, "\n{note}");
+    }
+    out
+}
+
+fn escape_markdown(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(
+            c,
+            '\\' | '*' | '_' | '`' | '|' | '[' | ']' | '<' | '>' | '#'
+        ) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+fn render_markdown(ctx: &NumberContext, info: &NumberInfo) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, "# {}", ctx.digits());
+    if !info.forms.is_empty() {
+        let _ = writeln!(out, "\n## Alternative forms\n");
+        let _ = writeln!(out, "| Form | Value |\n| --- | --- |");
+        for (desc, form) in &info.forms {
+            let _ = writeln!(
+                out,
+                "| {} | {} |",
+                escape_markdown(desc),
+                escape_markdown(&terminal_text(form))
+            );
+        }
+    }
+    for (category, facts) in &info.facts {
+        let _ = writeln!(out, "\n## {}\n", category.heading());
+        for fact in facts {
+            let _ = writeln!(out, "- {}", escape_markdown(&terminal_text(fact)));
+        }
+    }
+    for note in incomplete_notes(info) {
+        let _ = writeln!(out, "\n*{note}*");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::nerds::Category;
+
+    fn example() -> (NumberContext, NumberInfo) {
+        let ctx = NumberContext::new(Integer::from(8191));
+        let info = NumberInfo {
+            facts: vec![(
+                Category::Primality,
+                vec!["Is a Mersenne prime: (#2)(^(#13))-(#1)".to_owned()],
+            )],
+            forms: vec![("Binary".to_owned(), "1111111111111".to_owned())],
+            timed_out: vec!["factors"],
+            ..Default::default()
+        };
+        (ctx, info)
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse_u64_range("0..=10000"), Ok(0..=10000));
+        assert_eq!(parse_u64_range("5..10"), Ok(5..=9));
+        assert!(parse_u64_range("0..0").is_err());
+        assert!(parse_u64_range("10").is_err());
+        assert!(parse_u64_range("a..=b").is_err());
+        assert!(parse_u64_range("-1..=5").is_err());
+        // Precomputing isn't limited to 64 bits.
+        let huge = "18446744073709551616";
+        assert!(parse_u64_range(&format!("0..={huge}")).is_err());
+        let start = Integer::from(u64::MAX) + 1_u8;
+        assert_eq!(
+            parse_range(&format!("{huge}..{huge}1")),
+            Ok(start.clone()..=start * 10_u8)
+        );
+        assert!(parse_base_url("https://example.com/wiki/").is_ok());
+        assert!(parse_base_url("/wiki").is_err());
+        assert!(parse_base_url("").is_err());
+    }
+
+    #[test]
+    fn settings_override_config() {
+        let settings = Settings {
+            bind: Some("127.0.0.1:8080".to_owned()),
+            time_budget: Some(Duration::from_secs(1)),
+            disable_nerds: vec!["fibonacci".to_owned(), " prime".to_owned(), String::new()],
+            enable_nerds: vec!["prime".to_owned()],
+            ..Settings::default()
+        };
+        let config = settings.config().unwrap();
+        assert_eq!(config.bind, "127.0.0.1:8080");
+        assert_eq!(config.time_budget, Duration::from_secs(1));
+        assert_eq!(config.max_digits, Config::default().max_digits);
+        assert_eq!(
+            config.nerds,
+            [("fibonacci".to_owned(), false), ("prime".to_owned(), true)].into()
+        );
+
+        let settings = Settings {
+            random_digit_chance: Some(1.0),
+            ..Settings::default()
+        };
+        assert!(settings.config().is_err());
+        assert_eq!(parse_secs("0.25"), Ok(Duration::from_millis(250)));
+        assert!(parse_secs("-1").is_err());
+    }
+
+    #[test]
+    fn renders_text() {
+        let (ctx, info) = example();
+        assert_eq!(
+            render(Format::Text, &ctx, &info),
+            "8191\n\
+             \n\
+             Alternative forms\n  Binary: 1111111111111\n\
+             \n\
+             Primality\n  - Is a Mersenne prime: 2¹³-1\n\
+             \n\
+             These analyses ran out of time: factors.\n"
+        );
+    }
+
+    #[test]
+    fn renders_markdown() {
+        let (ctx, info) = example();
+        assert_eq!(
+            render(Format::Markdown, &ctx, &info),
+            "# 8191\n\
+             \n\
+             ## Alternative forms\n\
+             \n\
+             | Form | Value |\n| --- | --- |\n| Binary | 1111111111111 |\n\
+             \n\
+             ## Primality\n\
+             \n\
+             - Is a Mersenne prime: 2¹³-1\n\
+             \n\
+             *These analyses ran out of time: factors.*\n"
+        );
+    }
+}
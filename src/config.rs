@@ -0,0 +1,243 @@
+//! Settings, from a TOML file which may be overridden on the command line or in the environment.
+//!
+//! Every setting has a default, so the file only needs what should be different:
+//!
+//! ```toml
+//! bind = "127.0.0.1:8080"
+//! time_budget = 10.0
+//!
+//! [nerds]
+//! fibonacci = false
+//! ```
+//!
+//! Durations are in seconds.
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use rug::Integer;
+use serde::{Deserialize, Serialize};
+
+use crate::{math, nerds};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// The address the server listens on.
+    pub bind: String,
+    /// Larger numbers are rejected before the nerds are asked about them.
+    pub max_digits: u32,
+    /// How long the nerds may spend on a single number before the page is sent without them.
+    #[serde(with = "secs")]
+    pub time_budget: Duration,
+    /// How long to search for prime factors before settling for a partial factorization.
+    #[serde(with = "secs")]
+    pub factor_budget: Duration,
+    /// How long `precompute` lets the nerds spend on each number, which can be much longer
+    /// than a request may take, since the facts are stored for good.
+    #[serde(with = "secs")]
+    pub precompute_time_budget: Duration,
+    /// How long `precompute` searches for prime factors.
+    #[serde(with = "secs")]
+    pub precompute_factor_budget: Duration,
+    /// How long to wait for the nerds before sending a page without all of their facts.
+    #[serde(with = "secs")]
+    pub first_paint: Duration,
+    /// Number of Miller-Rabin rounds used by the primality test.
+    pub prime_rounds: u32,
+    /// The chance that `/random` adds another digit, so numbers have 1/(1-p) digits on average.
+    pub random_digit_chance: f64,
+    /// How many decimal digits worth of numbers to remember the facts about.
+    pub cache_capacity: usize,
+    /// The SQLite database in which to keep facts which were expensive to find.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub store: Option<PathBuf>,
+    /// Facts which took less time than this to find aren't worth storing.
+    #[serde(with = "secs")]
+    pub store_after: Duration,
+    /// Manual pages to use instead of the built-in ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manual_dir: Option<PathBuf>,
+    /// Assets to use instead of the built-in ones.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub assets_dir: Option<PathBuf>,
+    /// Whether to show the effective config at `/admin/config`.
+    /// Off by default, since it shows the server's file paths to anyone who can reach it.
+    pub admin_page: bool,
+    /// Whether each nerd should be asked, by name. Nerds which aren't listed are asked.
+    pub nerds: BTreeMap<String, bool>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "0.0.0.0:3000".to_owned(),
+            max_digits: 10_000,
+            time_budget: Duration::from_secs(5),
+            factor_budget: Duration::from_secs(2),
+            precompute_time_budget: Duration::from_secs(60),
+            precompute_factor_budget: Duration::from_secs(30),
+            first_paint: Duration::from_millis(200),
+            prime_rounds: 30,
+            random_digit_chance: 0.75,
+            cache_capacity: 1_000_000,
+            store: None,
+            store_after: Duration::from_millis(100),
+            manual_dir: None,
+            assets_dir: None,
+            admin_page: false,
+            nerds: BTreeMap::new(),
+        }
+    }
+}
+
+/// Durations as a number of seconds.
+mod secs {
+    use std::time::Duration;
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_f64(duration.as_secs_f64())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Duration, D::Error> {
+        let secs = f64::deserialize(d)?;
+        Duration::try_from_secs_f64(secs).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(&'static str),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, err) => write!(f, "cannot read {}: {err}", path.display()),
+            ConfigError::Parse(path, err) => {
+                write!(f, "invalid config in {}: {err}", path.display())
+            }
+            ConfigError::Invalid(problem) => write!(f, "invalid config: {problem}"),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the config file at `path`.
+    pub fn read(path: &Path) -> Result<Self, ConfigError> {
+        let source =
+            std::fs::read_to_string(path).map_err(|err| ConfigError::Read(path.to_owned(), err))?;
+        toml::from_str(&source).map_err(|err| ConfigError::Parse(path.to_owned(), err))
+    }
+
+    /// Checks for settings which would make the server misbehave.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !(0.0..1.0).contains(&self.random_digit_chance) {
+            return Err(ConfigError::Invalid(
+                "random_digit_chance must be at least 0 and less than 1",
+            ));
+        }
+        if self.prime_rounds == 0 {
+            return Err(ConfigError::Invalid("prime_rounds must be at least 1"));
+        }
+        Ok(())
+    }
+
+    /// Turns nerds on and off as configured, warning about names no nerd has.
+    pub fn apply_to(&self, registry: &mut nerds::Registry) {
+        for (name, &enabled) in &self.nerds {
+            if !registry.set_enabled(name, enabled) {
+                eprintln!("Warning: there is no nerd called {name:?}");
+            }
+        }
+    }
+
+    /// A context for analysing `n` within the configured limits.
+    pub fn number_context(&self, n: Integer) -> Arc<nerds::NumberContext> {
+        self.context(n, self.time_budget, self.factor_budget)
+    }
+
+    /// A context for analysing `n` ahead of time, within the larger limits for `precompute`.
+    pub fn precompute_context(&self, n: Integer) -> Arc<nerds::NumberContext> {
+        self.context(
+            n,
+            self.precompute_time_budget,
+            self.precompute_factor_budget,
+        )
+    }
+
+    fn context(
+        &self,
+        n: Integer,
+        time_budget: Duration,
+        factor_budget: Duration,
+    ) -> Arc<nerds::NumberContext> {
+        let ctx = nerds::NumberContext::new(n)
+            .with_budget(math::Budget::new(time_budget))
+            .with_factor_budget(factor_budget)
+            .with_prime_rounds(self.prime_rounds);
+        Arc::new(ctx)
+    }
+
+    /// The effective config in the same format as the file.
+    pub fn to_toml(&self) -> String {
+        // Note: This can't fail, the maps have string keys and the tables come last.
+        toml::to_string(self).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_partial_config() {
+        let config: Config = toml::from_str(
+            "bind = \"127.0.0.1:8080\"\ntime_budget = 0.5\nfirst_paint = 1\n[nerds]\nprime = false\n",
+        )
+        .unwrap();
+        assert_eq!(config.bind, "127.0.0.1:8080");
+        assert_eq!(config.time_budget, Duration::from_millis(500));
+        assert_eq!(config.first_paint, Duration::from_secs(1));
+        assert_eq!(config.nerds, BTreeMap::from([("prime".to_owned(), false)]));
+        assert_eq!(config.max_digits, Config::default().max_digits);
+
+        assert!(toml::from_str::<Config>("bnid = \"127.0.0.1:8080\"").is_err());
+        assert!(toml::from_str::<Config>("time_budget = -1").is_err());
+    }
+
+    #[test]
+    fn round_trips() {
+        let config = Config {
+            store: Some("facts.db".into()),
+            nerds: BTreeMap::from([("fibonacci".to_owned(), false)]),
+            ..Config::default()
+        };
+        assert_eq!(toml::from_str::<Config>(&config.to_toml()).unwrap(), config);
+    }
+
+    #[test]
+    fn validates() {
+        assert!(Config::default().validate().is_ok());
+        for config in [
+            Config {
+                random_digit_chance: 1.0,
+                ..Config::default()
+            },
+            Config {
+                prime_rounds: 0,
+                ..Config::default()
+            },
+        ] {
+            assert!(config.validate().is_err());
+        }
+    }
+}
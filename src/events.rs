@@ -0,0 +1,131 @@
+//! Streaming facts to pages which were sent before the nerds were done, as Server-Sent Events.
+//!
+//! Every fact is sent as a `fact` or `form` event as soon as it is heard.
+//! Once the nerds are done, a `done` event carries the finished results section of the page,
+//! with the facts in their proper order.
+use std::{convert::Infallible, sync::Arc};
+
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use futures_util::stream;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    filters::{self, LinkStyle},
+    input, manual,
+    nerds::{Fact, NumberContext, NumberInfo},
+    AppState,
+};
+
+#[derive(Template)]
+#[template(path = "int_results.html")]
+struct ResultsTemplate {
+    class_manuals: Vec<Arc<manual::Manual>>,
+    info: Arc<NumberInfo>,
+    links: LinkStyle,
+    pending: bool,
+}
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// How many facts the page already shows.
+    #[serde(default)]
+    seen: usize,
+}
+
+/// The event for a fact the page can show right away, if it is one.
+/// Classes only matter to the finished page, for its manual pages.
+fn fact_event(fact: &Fact) -> Option<Event> {
+    let links = LinkStyle::Absolute;
+    // Note: This can't fail, mathfmt always returns Ok.
+    let event = match fact {
+        Fact::Basic { category, text, .. } => Event::default().event("fact").data(
+            json!({
+                "heading": category.heading(),
+                "html": filters::mathfmt(text, &links).unwrap(),
+            })
+            .to_string(),
+        ),
+        Fact::Form {
+            description, form, ..
+        } => Event::default().event("form").data(
+            json!({
+                "description": description,
+                "html": filters::mathfmt(form, &links).unwrap(),
+            })
+            .to_string(),
+        ),
+        Fact::Class { .. } => return None,
+    };
+    Some(event)
+}
+
+fn done_event(ctx: &NumberContext, library: &manual::Library, info: Arc<NumberInfo>) -> Event {
+    let results = ResultsTemplate {
+        class_manuals: library.classes_of(ctx.n(), &info.classes),
+        info,
+        links: LinkStyle::Absolute,
+        pending: false,
+    };
+    // Note: This can't fail, the template's filters always return Ok.
+    Event::default()
+        .event("done")
+        .data(results.render().unwrap())
+}
+
+pub async fn handle_events(
+    State(state): State<Arc<AppState>>,
+    Path(param): Path<String>,
+    Query(query): Query<EventsQuery>,
+) -> Response {
+    let n = match input::parse(&param, state.config.max_digits) {
+        Ok((n, _)) => n,
+        Err(err) => return (err.status(), format!("Error: {err}")).into_response(),
+    };
+    let ctx = state.config.number_context(n);
+    let progress = crate::analyse(&state, &ctx);
+
+    let events = stream::unfold(
+        Some((state, ctx, progress, query.seen)),
+        |next| async move {
+            let (state, ctx, mut progress, mut seen) = next?;
+            loop {
+                let (info, event) = {
+                    let current = progress.borrow_and_update();
+                    let mut event = None;
+                    while event.is_none() {
+                        let Some(fact) = current.facts.get(seen) else {
+                            break;
+                        };
+                        event = fact_event(fact);
+                        seen += 1;
+                    }
+                    (current.info.clone(), event)
+                };
+                if let Some(event) = event {
+                    return Some((
+                        Ok::<_, Infallible>(event),
+                        Some((state, ctx, progress, seen)),
+                    ));
+                }
+                if let Some(info) = info {
+                    // The manual may have changed while the nerds were busy.
+                    let event = done_event(&ctx, &state.manual.current(), info);
+                    return Some((Ok(event), None));
+                }
+                // Note: This can't fail, the analysis always finishes before it lets go of the sender.
+                progress.changed().await.ok()?;
+            }
+        },
+    );
+    Sse::new(events)
+        .keep_alive(KeepAlive::default())
+        .into_response()
+}
@@ -1,24 +1,11 @@
-use std::{iter::Peekable, str::Chars};
+use std::{iter::Peekable, ops::RangeInclusive, str::Chars};
 
 type StrCursor<'a> = Peekable<Chars<'a>>;
 
-struct HtmlEmitter {
-    out: String,
-}
-
-impl HtmlEmitter {
-    /// Use in contexts where text is expected (anything except the interior attribute
-    /// section of a tag or closing tag.)
-    fn emit_text_char(&mut self, c: char) {
-        match c {
-            '&' => self.out.push_str("&amp;"),
-            '<' => self.out.push_str("&lt;"),
-            '>' => self.out.push_str("&gt;"),
-            '"' => self.out.push_str("&quot;"),
-            '\'' => self.out.push_str("&#39;"),
-            _ => self.out.push(c),
-        }
-    }
+/// Something the parsed markup is written out to.
+trait Emitter {
+    /// Use in contexts where text is expected.
+    fn emit_text_char(&mut self, c: char);
 
     fn emit_text_str(&mut self, s: &str) {
         for c in s.chars() {
@@ -26,6 +13,40 @@ impl HtmlEmitter {
         }
     }
 
+    fn start_superscript(&mut self);
+
+    fn end_superscript(&mut self);
+
+    /// A link to the page for the number with decimal digits `num`.
+    fn emit_num_link(&mut self, num: &str);
+}
+
+/// Where `(#k)` links point to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkStyle {
+    /// `/k`, as served by the web server.
+    Absolute,
+    /// `../k/`, for a static site with a directory for each number in the range.
+    /// Numbers outside the range don't have a page, so they are marked instead of linked.
+    Static(RangeInclusive<u64>),
+}
+
+impl LinkStyle {
+    /// Where the static file `name` is, from a number's page.
+    pub fn asset(&self, name: &str) -> String {
+        match self {
+            LinkStyle::Absolute => format!("/assets/{name}"),
+            LinkStyle::Static(_) => format!("../assets/{name}"),
+        }
+    }
+}
+
+struct HtmlEmitter<'a> {
+    out: String,
+    links: &'a LinkStyle,
+}
+
+impl HtmlEmitter<'_> {
     /// Lifetime is 'static to ensure that no user input is snuck into this function.
     fn emit_raw_str(&mut self, s: &'static str) {
         self.out.push_str(s);
@@ -44,16 +65,122 @@ impl HtmlEmitter {
     }
 }
 
-fn superscript_handler(s: &mut StrCursor, out: &mut HtmlEmitter) {
+impl Emitter for HtmlEmitter<'_> {
+    /// Use in contexts where text is expected (anything except the interior attribute
+    /// section of a tag or closing tag.)
+    fn emit_text_char(&mut self, c: char) {
+        match c {
+            '&' => self.out.push_str("&amp;"),
+            '<' => self.out.push_str("&lt;"),
+            '>' => self.out.push_str("&gt;"),
+            '"' => self.out.push_str("&quot;"),
+            '\'' => self.out.push_str("&#39;"),
+            _ => self.out.push(c),
+        }
+    }
+
+    fn start_superscript(&mut self) {
+        self.emit_raw_str("<sup>");
+    }
+
+    fn end_superscript(&mut self) {
+        self.emit_raw_str("</sup>");
+    }
+
+    fn emit_num_link(&mut self, num: &str) {
+        match self.links {
+            LinkStyle::Absolute => self.emit_link(&format!("/{num}"), num),
+            LinkStyle::Static(range) => {
+                if num.parse::<u64>().is_ok_and(|k| range.contains(&k)) {
+                    self.emit_link(&format!("../{num}/"), num);
+                } else {
+                    self.emit_raw_str("<span class=\"out-of-range\">");
+                    self.emit_text_str(num);
+                    self.emit_raw_str("</span>");
+                }
+            }
+        }
+    }
+}
+
+/// Writes unformatted text, e.g. `2^(n+1)` for `2(^n+1)`, and notes which numbers were linked to.
+#[derive(Default)]
+struct TextEmitter {
+    out: String,
+    numbers: Vec<String>,
+    /// Where each currently open superscript starts in `out`.
+    superscripts: Vec<usize>,
+    /// Write superscripts with Unicode superscript characters where possible, e.g. `2ⁿ⁺¹`.
+    unicode: bool,
+}
+
+/// The Unicode superscript version of `c`, if there is one.
+fn superscript_char(c: char) -> Option<char> {
+    Some(match c {
+        '0' => '⁰',
+        '1' => '¹',
+        '2' => '²',
+        '3' => '³',
+        '4' => '⁴',
+        '5' => '⁵',
+        '6' => '⁶',
+        '7' => '⁷',
+        '8' => '⁸',
+        '9' => '⁹',
+        '+' => '⁺',
+        '-' => '⁻',
+        '=' => '⁼',
+        '(' => '⁽',
+        ')' => '⁾',
+        'i' => 'ⁱ',
+        'n' => 'ⁿ',
+        _ => return None,
+    })
+}
+
+impl Emitter for TextEmitter {
+    fn emit_text_char(&mut self, c: char) {
+        self.out.push(c);
+    }
+
+    fn start_superscript(&mut self) {
+        self.superscripts.push(self.out.len());
+    }
+
+    fn end_superscript(&mut self) {
+        let start = self.superscripts.pop().unwrap_or(self.out.len());
+        if self.unicode {
+            let unicode: Option<String> = self.out[start..].chars().map(superscript_char).collect();
+            if let Some(unicode) = unicode {
+                self.out.replace_range(start.., &unicode);
+                return;
+            }
+        }
+        // Exponents which aren't a single number or word need parentheses to be unambiguous.
+        if self.out[start..].chars().all(char::is_alphanumeric) {
+            self.out.insert(start, '^');
+        } else {
+            self.out.insert_str(start, "^(");
+            self.out.push(')');
+        }
+    }
+
+    fn emit_num_link(&mut self, num: &str) {
+        self.out.push_str(num);
+        self.numbers.push(num.to_owned());
+    }
+}
+
+fn superscript_handler(s: &mut StrCursor, out: &mut impl Emitter) {
     s.next();
-    out.emit_raw_str("<sup>");
+    out.start_superscript();
     parse_balanced_parens(s, out);
     // eat closing paren
     s.next();
-    out.emit_raw_str("</sup>");
+    out.end_superscript();
 }
 
-fn num_link_handler(s: &mut StrCursor, out: &mut HtmlEmitter) {
+fn num_link_handler(s: &mut StrCursor, out: &mut impl Emitter) {
     s.next();
     let mut num = String::with_capacity(16);
     for c in s.by_ref() {
@@ -62,7 +189,7 @@ fn num_link_handler(s: &mut StrCursor, out: &mut HtmlEmitter) {
                 num.push(c);
             }
             ')' => {
-                out.emit_link(&format!("/{num}"), &num);
+                out.emit_num_link(&num);
                 return;
             }
             _ => {
@@ -75,7 +202,7 @@ fn num_link_handler(s: &mut StrCursor, out: &mut HtmlEmitter) {
     }
 }
 
-fn parse_balanced_parens(s: &mut StrCursor, out: &mut HtmlEmitter) {
+fn parse_balanced_parens(s: &mut StrCursor, out: &mut impl Emitter) {
     let mut brack_count = 0u32;
     while let Some(&c) = s.peek() {
         match c {
@@ -116,30 +243,52 @@ fn parse_balanced_parens(s: &mut StrCursor, out: &mut HtmlEmitter) {
     }
 }
 
-fn run_filter(s: &mut StrCursor) -> String {
-    let mut res = HtmlEmitter { out: String::new() };
+fn run_filter(s: &mut StrCursor, res: &mut impl Emitter) {
     loop {
         match s.peek() {
             Some(')') => {
                 res.emit_text_char(')');
                 s.next();
             }
-            Some(_) => parse_balanced_parens(s, &mut res),
+            Some(_) => parse_balanced_parens(s, res),
             None => break,
         }
     }
-    res.out
 }
 
-pub fn mathfmt<T: std::fmt::Display>(s: T) -> askama::Result<String> {
-    Ok(run_filter(&mut s.to_string().chars().peekable()))
+pub fn mathfmt<T: std::fmt::Display>(s: T, links: &LinkStyle) -> askama::Result<String> {
+    let mut res = HtmlEmitter {
+        out: String::new(),
+        links,
+    };
+    run_filter(&mut s.to_string().chars().peekable(), &mut res);
+    Ok(res.out)
+}
+
+/// Strips the markup from `s`, returning the plain text
+/// and the numbers it links to, in order of appearance.
+pub fn plain_text(s: &str) -> (String, Vec<String>) {
+    let mut res = TextEmitter::default();
+    run_filter(&mut s.chars().peekable(), &mut res);
+    (res.out, res.numbers)
+}
+
+/// Strips the markup from `s` for showing in a terminal,
+/// using Unicode superscripts where possible.
+pub fn terminal_text(s: &str) -> String {
+    let mut res = TextEmitter {
+        unicode: true,
+        ..Default::default()
+    };
+    run_filter(&mut s.chars().peekable(), &mut res);
+    res.out
 }
 
 #[test]
 fn mathfmt_test() {
     macro_rules! check {
         ($a:expr, $b:expr) => {
-            assert_eq!(mathfmt($a).unwrap(), $b)
+            assert_eq!(mathfmt($a, &LinkStyle::Absolute).unwrap(), $b)
         };
     }
     check!(
@@ -178,3 +327,43 @@ fn mathfmt_test() {
         "links fail without parens #11"
     );
 }
+
+#[test]
+fn static_links_test() {
+    let links = LinkStyle::Static(10..=20);
+    assert_eq!(
+        mathfmt("(#12)(^(#2))", &links).unwrap(),
+        "<a href=\"../12/\">12</a><sup><span class=\"out-of-range\">2</span></sup>"
+    );
+}
+
+#[test]
+fn plain_text_test() {
+    macro_rules! check {
+        ($a:expr, $b:expr, $c:expr) => {
+            assert_eq!(
+                plain_text($a),
+                (
+                    $b.to_owned(),
+                    $c.iter().map(|s: &&str| s.to_string()).collect()
+                )
+            )
+        };
+    }
+    let none: [&str; 0] = [];
+    check!(r#"<html>&"#, "<html>&", none);
+    check!("super(^script)", "super^script", none);
+    check!("parens(^bal(an)ce)", "parens^(bal(an)ce)", none);
+    check!("not(\\^superscript)", "not(^superscript)", none);
+    check!("links (#537) to numbers", "links 537 to numbers", ["537"]);
+    check!("(#2)(^(#13))-(#1)", "2^13-1", ["2", "13", "1"]);
+    check!("(#2)(^(#13)+1)", "2^(13+1)", ["2", "13"]);
+}
+
+#[test]
+fn terminal_text_test() {
+    assert_eq!(terminal_text("(#2)(^(#13))-(#1)"), "2¹³-1");
+    assert_eq!(terminal_text("x(^n+1)"), "xⁿ⁺¹");
+    assert_eq!(terminal_text("x(^y)"), "x^y");
+    assert_eq!(terminal_text("x(^2(^k))"), "x^(2^k)");
+}
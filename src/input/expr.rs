@@ -0,0 +1,566 @@
+//! Arithmetic expressions like `2^127-1`, `10!`, `1e6` or `binomial(10, 3)`.
+//!
+//! Expressions are evaluated while they are parsed. Every intermediate value is
+//! checked against `MAX_BITS`, and operations whose result would obviously be
+//! larger are refused before doing any work, so evaluation stays cheap.
+use std::{fmt, sync::OnceLock};
+
+use rug::{ops::Pow, Complete, Integer};
+
+use super::numeral::{parse_digits, radix_prefix};
+use crate::math::factorize::{sieve, small_primes};
+
+/// Values may have at most this many bits, which is about 315,000 decimal digits.
+pub const MAX_BITS: u32 = 1 << 20;
+/// The longest expression we'll try to evaluate, in bytes.
+pub const MAX_LEN: usize = 1000;
+/// How deeply parentheses, function calls and signs may be nested.
+const MAX_DEPTH: u32 = 64;
+/// `prime(k)` looks the `k`th prime up in a sieve, so `k` is limited to keep that small.
+const MAX_PRIME_INDEX: u32 = 1_000_000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprError {
+    /// The input isn't a well formed expression.
+    /// `pos` is the number of characters before the problem.
+    Syntax { pos: usize, expected: &'static str },
+    /// The expression has no integer value, e.g. `1/0`.
+    Undefined(&'static str),
+    /// The expression, its result or a value on the way to it is too large.
+    TooLarge,
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprError::Syntax { pos, expected } => {
+                write!(f, "expected {expected} at position {pos}")
+            }
+            ExprError::Undefined(reason) => f.write_str(reason),
+            ExprError::TooLarge => f.write_str("it is too large to evaluate"),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, ExprError>;
+
+/// Evaluates an expression made of natural numbers, `+ - * / ^ !`, parentheses,
+/// numbers with a radix prefix like `0xFF` or separated digits like `1_000`,
+/// and the functions `fib(n)`, `lucas(n)`, `prime(k)` and `binomial(n, k)`.
+///
+/// Division must be exact. The result may be negative.
+pub fn evaluate(input: &str) -> Result<Integer> {
+    if input.len() > MAX_LEN {
+        return Err(ExprError::TooLarge);
+    }
+    let mut parser = Parser {
+        src: input,
+        pos: 0,
+        depth: 0,
+    };
+    let value = parser.expr()?;
+    parser.skip_whitespace();
+    if parser.pos < input.len() {
+        return Err(parser.expected("an operator"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    /// Byte offset of the next unparsed character.
+    pos: usize,
+    depth: u32,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.src[self.pos..].chars().next() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    /// The next non-whitespace byte.
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.src.as_bytes().get(self.pos).copied()
+    }
+
+    /// Consumes `c` if it is next.
+    fn eat(&mut self, c: u8) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expected(&self, expected: &'static str) -> ExprError {
+        ExprError::Syntax {
+            pos: self.src[..self.pos].chars().count(),
+            expected,
+        }
+    }
+
+    fn expect(&mut self, c: u8, expected: &'static str) -> Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.expected(expected))
+        }
+    }
+
+    /// Runs `f` one level deeper, so that deeply nested input can't overflow the stack.
+    fn nested<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth >= MAX_DEPTH {
+            return Err(ExprError::TooLarge);
+        }
+        self.depth += 1;
+        let res = f(self);
+        self.depth -= 1;
+        res
+    }
+
+    /// expr = term (('+' | '-') term)*
+    fn expr(&mut self) -> Result<Integer> {
+        let mut acc = self.term()?;
+        loop {
+            if self.eat(b'+') {
+                acc += self.term()?;
+            } else if self.eat(b'-') {
+                acc -= self.term()?;
+            } else {
+                return Ok(acc);
+            }
+            acc = checked(acc)?;
+        }
+    }
+
+    /// term = unary (('*' | '/') unary)*
+    fn term(&mut self) -> Result<Integer> {
+        let mut acc = self.unary()?;
+        loop {
+            if self.eat(b'*') {
+                let rhs = self.unary()?;
+                if acc.significant_bits() + rhs.significant_bits() > MAX_BITS + 1 {
+                    return Err(ExprError::TooLarge);
+                }
+                acc *= rhs;
+            } else if self.eat(b'/') {
+                let rhs = self.unary()?;
+                if rhs.is_zero() {
+                    return Err(ExprError::Undefined("it divides by zero"));
+                }
+                let (quotient, rem) = acc.div_rem(rhs);
+                if !rem.is_zero() {
+                    return Err(ExprError::Undefined("a division leaves a remainder"));
+                }
+                acc = quotient;
+            } else {
+                return Ok(acc);
+            }
+            acc = checked(acc)?;
+        }
+    }
+
+    /// unary = ('-' | '+') unary | power
+    fn unary(&mut self) -> Result<Integer> {
+        if self.eat(b'-') {
+            self.nested(|p| p.unary()).map(|n| -n)
+        } else if self.eat(b'+') {
+            self.nested(|p| p.unary())
+        } else {
+            self.power()
+        }
+    }
+
+    /// power = postfix ('^' unary)?
+    fn power(&mut self) -> Result<Integer> {
+        let base = self.postfix()?;
+        if !self.eat(b'^') {
+            return Ok(base);
+        }
+        let exp = self.nested(|p| p.unary())?;
+        pow(base, exp)
+    }
+
+    /// postfix = primary '!'*
+    fn postfix(&mut self) -> Result<Integer> {
+        let mut value = self.primary()?;
+        while self.eat(b'!') {
+            value = factorial(&value)?;
+        }
+        Ok(value)
+    }
+
+    /// primary = number | '(' expr ')' | name '(' expr (',' expr)* ')'
+    fn primary(&mut self) -> Result<Integer> {
+        match self.peek() {
+            Some(b'(') => {
+                self.pos += 1;
+                let value = self.nested(|p| p.expr())?;
+                self.expect(b')', "a closing parenthesis")?;
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.call(),
+            _ => Err(self.expected("a number")),
+        }
+    }
+
+    /// Digits with an optional radix prefix, or decimal digits followed by `e` and more digits
+    /// for scientific notation. Digits may be separated by underscores.
+    fn number(&mut self) -> Result<Integer> {
+        let start = self.pos;
+        if let Some((radix, _)) = radix_prefix(&self.src[self.pos..]) {
+            self.pos += 2;
+            let digits = self.take_while(|c| c.is_ascii_alphanumeric() || c == b'_');
+            return match parse_digits(digits, radix) {
+                Some(n) => checked(n),
+                None => {
+                    self.pos = start;
+                    Err(self.expected("digits matching the radix prefix"))
+                }
+            };
+        }
+        let digits = self.take_while(|c| c.is_ascii_digit() || c == b'_');
+        let Some(mantissa) = parse_digits(digits, 10) else {
+            self.pos = start;
+            return Err(self.expected("a number"));
+        };
+        if !matches!(self.src.as_bytes().get(self.pos), Some(b'e' | b'E')) {
+            return checked(mantissa);
+        }
+        self.pos += 1;
+        let exp = self.take_while(|c| c.is_ascii_digit());
+        if exp.is_empty() {
+            return Err(self.expected("an exponent"));
+        }
+        // Note: This can't fail, since exp is non-empty and only contains digits.
+        let scale = pow(Integer::from(10), Integer::parse(exp).unwrap().complete())?;
+        if mantissa.significant_bits() + scale.significant_bits() > MAX_BITS + 1 {
+            return Err(ExprError::TooLarge);
+        }
+        checked(mantissa * scale)
+    }
+
+    fn take_while(&mut self, f: impl Fn(u8) -> bool) -> &str {
+        let start = self.pos;
+        while self.src.as_bytes().get(self.pos).is_some_and(|&c| f(c)) {
+            self.pos += 1;
+        }
+        &self.src[start..self.pos]
+    }
+
+    fn call(&mut self) -> Result<Integer> {
+        let start = self.pos;
+        let name = self.take_while(|c| c.is_ascii_alphabetic());
+        let arity = match name {
+            "fib" | "lucas" | "prime" => 1,
+            "binomial" => 2,
+            _ => {
+                self.pos = start;
+                return Err(self.expected("one of the functions fib, lucas, prime or binomial"));
+            }
+        };
+        let name = name.to_owned();
+
+        self.expect(b'(', "an opening parenthesis")?;
+        let mut args = Vec::with_capacity(arity);
+        for i in 0..arity {
+            if i > 0 {
+                self.expect(b',', "another argument")?;
+            }
+            args.push(self.nested(|p| p.expr())?);
+        }
+        self.expect(b')', "a closing parenthesis")?;
+
+        match (name.as_str(), &args[..]) {
+            ("fib", [n]) => fibonacci(n, |n| Integer::fibonacci(n).complete()),
+            ("lucas", [n]) => fibonacci(n, |n| Integer::lucas(n).complete()),
+            ("prime", [k]) => nth_prime(k),
+            ("binomial", [n, k]) => binomial(n, k),
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn checked(n: Integer) -> Result<Integer> {
+    if n.significant_bits() > MAX_BITS {
+        Err(ExprError::TooLarge)
+    } else {
+        Ok(n)
+    }
+}
+
+fn pow(base: Integer, exp: Integer) -> Result<Integer> {
+    // 0^0 is taken to be 1.
+    if exp.is_zero() || base == 1 {
+        return Ok(Integer::from(1));
+    }
+    if base == -1 {
+        return Ok(if exp.is_even() {
+            Integer::from(1)
+        } else {
+            base
+        });
+    }
+    if exp.is_negative() {
+        return Err(ExprError::Undefined("it has a negative exponent"));
+    }
+    if base.is_zero() {
+        return Ok(base);
+    }
+    let exp = exp.to_u32().ok_or(ExprError::TooLarge)?;
+    // |base| >= 2 here, so the result has at least (bits - 1) * exp bits.
+    if u64::from(base.significant_bits() - 1) * u64::from(exp) > u64::from(MAX_BITS) {
+        return Err(ExprError::TooLarge);
+    }
+    checked(base.pow(exp))
+}
+
+fn factorial(n: &Integer) -> Result<Integer> {
+    if n.is_negative() {
+        return Err(ExprError::Undefined(
+            "it has the factorial of a negative number",
+        ));
+    }
+    let n = n.to_u32().ok_or(ExprError::TooLarge)?;
+    // Stirling's approximation, log2(n!) ~ n log2(n / e), which is good enough for a limit.
+    let bits = f64::from(n) * (f64::from(n) / std::f64::consts::E).log2();
+    if bits > f64::from(MAX_BITS) {
+        return Err(ExprError::TooLarge);
+    }
+    Ok(Integer::factorial(n).complete())
+}
+
+/// `fib(n)` or `lucas(n)`, depending on `f`.
+fn fibonacci(n: &Integer, f: impl FnOnce(u32) -> Integer) -> Result<Integer> {
+    if n.is_negative() {
+        return Err(ExprError::Undefined(
+            "it has a sequence with a negative index",
+        ));
+    }
+    // Both grow like phi^n, and log2(phi) < 0.7.
+    let n = n.to_u32().ok_or(ExprError::TooLarge)?;
+    if f64::from(n) * 0.7 > f64::from(MAX_BITS) {
+        return Err(ExprError::TooLarge);
+    }
+    Ok(f(n))
+}
+
+fn nth_prime(k: &Integer) -> Result<Integer> {
+    if *k < 1 {
+        return Err(ExprError::Undefined("primes are counted from prime(1) = 2"));
+    }
+    let k = k
+        .to_u32()
+        .filter(|&k| k <= MAX_PRIME_INDEX)
+        .ok_or(ExprError::TooLarge)? as usize;
+    let p = match small_primes().get(k - 1) {
+        Some(&p) => p,
+        None => indexed_primes()[k - 1],
+    };
+    Ok(Integer::from(p))
+}
+
+/// The first `MAX_PRIME_INDEX` primes, sieved on first use.
+fn indexed_primes() -> &'static [u32] {
+    static PRIMES: OnceLock<Vec<u32>> = OnceLock::new();
+    PRIMES.get_or_init(|| {
+        // The kth prime is below k (ln k + ln ln k) for k >= 6.
+        let k = f64::from(MAX_PRIME_INDEX);
+        let k_ln = k.ln();
+        let mut primes = sieve((k * (k_ln + k_ln.ln())) as u32 + 1);
+        primes.truncate(MAX_PRIME_INDEX as usize);
+        primes
+    })
+}
+
+fn binomial(n: &Integer, k: &Integer) -> Result<Integer> {
+    if n.is_negative() || k.is_negative() {
+        return Err(ExprError::Undefined(
+            "it has a binomial of negative numbers",
+        ));
+    }
+    if k > n {
+        return Ok(Integer::new());
+    }
+    // binomial(n, k) = binomial(n, n - k), and is at most min(2^n, n^k).
+    let k = (n - k).complete().min(k.clone());
+    let k = k.to_u32().ok_or(ExprError::TooLarge)?;
+    if u64::from(n.significant_bits()) * u64::from(k) > u64::from(MAX_BITS) && *n > MAX_BITS {
+        return Err(ExprError::TooLarge);
+    }
+    checked(n.binomial_ref(k).complete())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    macro_rules! check {
+        ($a:expr, $b:expr) => {
+            assert_eq!(evaluate($a), Ok(Integer::parse($b).unwrap().complete()))
+        };
+    }
+
+    macro_rules! check_err {
+        ($a:expr, $b:expr) => {
+            assert_eq!(evaluate($a), Err($b))
+        };
+    }
+
+    #[test]
+    fn arithmetic() {
+        check!("1+2*3", "7");
+        check!("(1+2)*3", "9");
+        check!("10-4-3", "3");
+        check!("100/5/2", "10");
+        check!("2^3^2", "512");
+        check!("-2^2", "-4");
+        check!("(-2)^3", "-8");
+        check!("2^-0", "1");
+        check!("0^0", "1");
+        check!(" 2 ^ 127 - 1 ", "170141183460469231731687303715884105727");
+        // Non-breaking and ideographic spaces are more than one byte long.
+        check!("1\u{a0}+\u{3000}1", "2");
+        check!("10!", "3628800");
+        check!("3!!", "720");
+        check!("1e6", "1000000");
+        check!("25E2+1", "2501");
+        check!("0xff+0b11*0o10", "279");
+        check!("1_000*2", "2000");
+    }
+
+    #[test]
+    fn functions() {
+        check!("fib(100)", "354224848179261915075");
+        check!("lucas(10)", "123");
+        check!("prime(1)", "2");
+        check!("prime(100000)", "1299709");
+        check!("prime(1000000)", "15485863");
+        // The first prime past the small primes.
+        check!("prime(82026)", "1048583");
+        check!("binomial(10, 3)", "120");
+        check!("binomial(3, 10)", "0");
+        check!("binomial(fib(10), 2)*2", "2970");
+    }
+
+    #[test]
+    fn errors() {
+        check_err!(
+            "",
+            ExprError::Syntax {
+                pos: 0,
+                expected: "a number"
+            }
+        );
+        check_err!(
+            "2+",
+            ExprError::Syntax {
+                pos: 2,
+                expected: "a number"
+            }
+        );
+        check_err!(
+            "(2",
+            ExprError::Syntax {
+                pos: 2,
+                expected: "a closing parenthesis"
+            }
+        );
+        check_err!(
+            "2 3",
+            ExprError::Syntax {
+                pos: 2,
+                expected: "an operator"
+            }
+        );
+        check_err!(
+            "2\u{a0}\u{a0}3",
+            ExprError::Syntax {
+                pos: 3,
+                expected: "an operator"
+            }
+        );
+        check_err!(
+            "1e",
+            ExprError::Syntax {
+                pos: 2,
+                expected: "an exponent"
+            }
+        );
+        check_err!(
+            "sqrt(4)",
+            ExprError::Syntax {
+                pos: 0,
+                expected: "one of the functions fib, lucas, prime or binomial"
+            }
+        );
+        check_err!(
+            "binomial(4)",
+            ExprError::Syntax {
+                pos: 10,
+                expected: "another argument"
+            }
+        );
+        check_err!("1/0", ExprError::Undefined("it divides by zero"));
+        check_err!("7/2", ExprError::Undefined("a division leaves a remainder"));
+        check_err!("2^-1", ExprError::Undefined("it has a negative exponent"));
+        check_err!(
+            "(-1)!",
+            ExprError::Undefined("it has the factorial of a negative number")
+        );
+        check_err!(
+            "prime(0)",
+            ExprError::Undefined("primes are counted from prime(1) = 2")
+        );
+    }
+
+    #[test]
+    fn literals() {
+        check_err!(
+            "1+0b12",
+            ExprError::Syntax {
+                pos: 2,
+                expected: "digits matching the radix prefix"
+            }
+        );
+        check_err!(
+            "1__0",
+            ExprError::Syntax {
+                pos: 0,
+                expected: "a number"
+            }
+        );
+    }
+
+    #[test]
+    fn limits() {
+        check_err!("2^2^2^2^2^2", ExprError::TooLarge);
+        check_err!("100000!", ExprError::TooLarge);
+        check_err!("10!!", ExprError::TooLarge);
+        check_err!("fib(10^9)", ExprError::TooLarge);
+        check_err!("prime(10^7)", ExprError::TooLarge);
+        check_err!("binomial(2^2^30, 2^20)", ExprError::TooLarge);
+        check_err!("1e1000000", ExprError::TooLarge);
+        check_err!("(2^1000000)*(2^1000000)", ExprError::TooLarge);
+        check_err!(&"(".repeat(100), ExprError::TooLarge);
+        check_err!(&"1+".repeat(MAX_LEN), ExprError::TooLarge);
+        assert!(evaluate("2^1000000").is_ok());
+    }
+
+    #[test]
+    fn matches_integer_arithmetic() {
+        proptest!(|(a in any::<i64>(), b in any::<i64>(), c in 0..100_u32)| {
+            let expected = Integer::from(a) * 3 - Integer::from(b).pow(2) + Integer::from(c).pow(c);
+            prop_assert_eq!(evaluate(&format!("({a})*3-({b})^2+{c}^{c}")), Ok(expected));
+        });
+    }
+}
@@ -0,0 +1,213 @@
+//! Turning what the user typed into a number.
+use std::fmt;
+
+use axum::http::StatusCode;
+use rug::{Complete, Integer};
+use serde::{Deserialize, Serialize};
+
+mod expr;
+mod numeral;
+
+pub use expr::ExprError;
+
+/// Inputs may be this many characters long for every digit allowed in the number.
+/// Binary takes a bit over three characters per decimal digit, and separators take some more.
+const CHARS_PER_DIGIT: usize = 4;
+
+/// How the user wrote a number.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Notation {
+    Decimal,
+    Binary,
+    Octal,
+    Hexadecimal,
+    /// Decimal, with digits separated by commas or underscores.
+    Grouped,
+    Roman,
+    Expression,
+}
+
+impl Notation {
+    /// Completes the sentence "Your input was read as ...".
+    pub fn description(&self) -> &'static str {
+        match self {
+            Notation::Decimal => "a decimal number",
+            Notation::Binary => "a binary number",
+            Notation::Octal => "an octal number",
+            Notation::Hexadecimal => "a hexadecimal number",
+            Notation::Grouped => "a decimal number with digit separators",
+            Notation::Roman => "Roman numerals",
+            Notation::Expression => "an arithmetic expression",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputError {
+    /// The input isn't a number, or an expression which could be evaluated.
+    Expression { input: String, error: ExprError },
+    /// The input is a number, but not a natural one.
+    Negative(Integer),
+    /// The input is too long to even try reading it.
+    TooLong { max_len: usize },
+    /// The number has more digits than we are willing to analyse.
+    TooManyDigits { max_digits: u32 },
+}
+
+impl InputError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            InputError::Expression {
+                error: ExprError::TooLarge,
+                ..
+            }
+            | InputError::TooManyDigits { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            InputError::TooLong { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// A short, stable identifier for the kind of error, for API clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            InputError::Expression { error, .. } => match error {
+                ExprError::Syntax { .. } => "not_a_number",
+                ExprError::Undefined(_) => "undefined",
+                ExprError::TooLarge => "too_large",
+            },
+            InputError::Negative(_) => "negative",
+            InputError::TooLong { .. } => "too_long",
+            InputError::TooManyDigits { .. } => "too_many_digits",
+        }
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputError::Expression { input, error } => {
+                write!(
+                    f,
+                    "{input:?} could not be evaluated as a natural number: {error}."
+                )
+            }
+            InputError::Negative(n) => write!(f, "{n} is not a natural number."),
+            InputError::TooLong { max_len } => {
+                write!(f, "The input is longer than {max_len} characters.")
+            }
+            InputError::TooManyDigits { max_digits } => {
+                write!(f, "The number has more than {max_digits} digits.")
+            }
+        }
+    }
+}
+
+/// Parses a natural number, and tells how it was written.
+///
+/// Besides decimal, this understands radix prefixes like `0xFF`, digit separators like `1,000`,
+/// Roman numerals, and arithmetic expressions such as `2^127-1`.
+/// Numbers with more than `max_digits` decimal digits are rejected.
+pub fn parse(input: &str, max_digits: u32) -> Result<(Integer, Notation), InputError> {
+    let max_len = (max_digits as usize).saturating_mul(CHARS_PER_DIGIT);
+    if input.len() > max_len {
+        return Err(InputError::TooLong { max_len });
+    }
+    // Plain numbers can be longer than expressions are allowed to be.
+    let (n, notation) = if !input.is_empty() && input.bytes().all(|c| c.is_ascii_digit()) {
+        // Note: This can't fail, since input is made of digits.
+        (Integer::parse(input).unwrap().complete(), Notation::Decimal)
+    } else if let Some(parsed) = numeral::parse(input) {
+        parsed
+    } else {
+        let n = expr::evaluate(input).map_err(|error| InputError::Expression {
+            input: input.to_owned(),
+            error,
+        })?;
+        (n, Notation::Expression)
+    };
+    if n.is_negative() {
+        return Err(InputError::Negative(n));
+    }
+    if n >= Integer::u_pow_u(10, max_digits).complete() {
+        return Err(InputError::TooManyDigits { max_digits });
+    }
+    Ok((n, notation))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_DIGITS: u32 = 1000;
+
+    #[test]
+    fn parses_naturals() {
+        assert_eq!(
+            parse("0", MAX_DIGITS),
+            Ok((Integer::ZERO, Notation::Decimal))
+        );
+        assert_eq!(
+            parse("1234", MAX_DIGITS),
+            Ok((Integer::from(1234), Notation::Decimal))
+        );
+        assert_eq!(
+            parse("-3", MAX_DIGITS),
+            Err(InputError::Negative(Integer::from(-3)))
+        );
+        assert_eq!(
+            parse("2^4-1", MAX_DIGITS),
+            Ok((Integer::from(15), Notation::Expression))
+        );
+        assert_eq!(
+            parse("0x10", MAX_DIGITS),
+            Ok((Integer::from(16), Notation::Hexadecimal))
+        );
+        assert_eq!(
+            parse("0x10+1_000", MAX_DIGITS),
+            Ok((Integer::from(1016), Notation::Expression))
+        );
+        assert_eq!(
+            parse("IV", MAX_DIGITS),
+            Ok((Integer::from(4), Notation::Roman))
+        );
+        assert_eq!(
+            parse("1-2", MAX_DIGITS),
+            Err(InputError::Negative(Integer::from(-1)))
+        );
+        assert_eq!(parse("abc", MAX_DIGITS).unwrap_err().code(), "not_a_number");
+        assert_eq!(parse("", MAX_DIGITS).unwrap_err().code(), "not_a_number");
+        assert_eq!(parse("1/0", MAX_DIGITS).unwrap_err().code(), "undefined");
+        assert_eq!(
+            parse("9^9^9", MAX_DIGITS).unwrap_err().status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+
+    #[test]
+    fn limits_size() {
+        assert_eq!(
+            parse("9999", 4),
+            Ok((Integer::from(9999), Notation::Decimal))
+        );
+        assert_eq!(
+            parse("10000", 4),
+            Err(InputError::TooManyDigits { max_digits: 4 })
+        );
+        assert_eq!(
+            parse("10^4", 4),
+            Err(InputError::TooManyDigits { max_digits: 4 })
+        );
+        assert_eq!(
+            parse("0b10011100001111", 4),
+            Ok((Integer::from(9999), Notation::Binary))
+        );
+        let err = parse("00000000000000001", 4).unwrap_err();
+        assert_eq!(err, InputError::TooLong { max_len: 16 });
+        assert_eq!(err.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(
+            InputError::TooManyDigits { max_digits: 4 }.status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+    }
+}
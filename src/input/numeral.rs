@@ -0,0 +1,89 @@
+//! Ways of writing a single number other than plain decimal digits.
+use rug::Integer;
+
+use super::Notation;
+use crate::nerds::decode_roman;
+
+/// Parses digits in the given radix, which may be separated by single underscores like `1_000`.
+pub fn parse_digits(s: &str, radix: i32) -> Option<Integer> {
+    if s.is_empty() || s.starts_with('_') || s.ends_with('_') || s.contains("__") {
+        return None;
+    }
+    let digits = s.replace('_', "");
+    if !digits.chars().all(|c| c.is_digit(radix as u32)) {
+        return None;
+    }
+    Integer::from_str_radix(&digits, radix).ok()
+}
+
+/// The radix for a prefix like `0x`, along with the notation it stands for.
+pub fn radix_prefix(s: &str) -> Option<(i32, Notation)> {
+    let prefix = s.get(..2)?.to_ascii_lowercase();
+    match prefix.as_str() {
+        "0b" => Some((2, Notation::Binary)),
+        "0o" => Some((8, Notation::Octal)),
+        "0x" => Some((16, Notation::Hexadecimal)),
+        _ => None,
+    }
+}
+
+/// Decimal digits grouped in threes by commas, like `1,000,000`.
+fn parse_comma_grouped(s: &str) -> Option<Integer> {
+    let mut groups = s.split(',');
+    let first = groups.next()?;
+    let mut digits = first.to_owned();
+    if !(1..=3).contains(&first.len()) {
+        return None;
+    }
+    let mut grouped = false;
+    for group in groups {
+        if group.len() != 3 {
+            return None;
+        }
+        digits.push_str(group);
+        grouped = true;
+    }
+    if !grouped {
+        return None;
+    }
+    parse_digits(&digits, 10)
+}
+
+/// Recognises a single number written with a radix prefix, digit separators or Roman numerals.
+pub fn parse(s: &str) -> Option<(Integer, Notation)> {
+    if let Some((radix, notation)) = radix_prefix(s) {
+        return parse_digits(&s[2..], radix).map(|n| (n, notation));
+    }
+    if let Some(n) = parse_comma_grouped(s).or_else(|| parse_digits(s, 10)) {
+        return Some((n, Notation::Grouped));
+    }
+    decode_roman(s).map(|n| (Integer::from(n), Notation::Roman))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numerals() {
+        let check = |s: &str, n: u32, notation| {
+            assert_eq!(parse(s), Some((Integer::from(n), notation)), "{s}");
+        };
+        check("0xFF", 255, Notation::Hexadecimal);
+        check("0Xff", 255, Notation::Hexadecimal);
+        check("0b1011", 11, Notation::Binary);
+        check("0o777", 511, Notation::Octal);
+        check("0xdead_beef", 0xdead_beef, Notation::Hexadecimal);
+        check("1,000,000", 1_000_000, Notation::Grouped);
+        check("12,345", 12_345, Notation::Grouped);
+        check("1_000_000", 1_000_000, Notation::Grouped);
+        check("MMXXIV", 2024, Notation::Roman);
+        check("xlii", 42, Notation::Roman);
+
+        for s in [
+            "0x", "0xG", "0b12", "0x_1", "1,00", "1000,000", "1,000,", "1__0", "_1", "IIII", "1.5",
+        ] {
+            assert_eq!(parse(s), None, "{s}");
+        }
+    }
+}
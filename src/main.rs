@@ -1,65 +1,297 @@
-use std::sync::Arc;
+use std::{process::ExitCode, sync::Arc};
 
 use askama::Template;
-use axum::{extract::Path, http::StatusCode, response::Redirect, routing::get, Router};
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+use clap::Parser;
 use rand::Rng;
-use rug::Integer;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
 
+mod api;
+mod assets;
+mod cache;
+mod cli;
+mod config;
+mod events;
 mod filters;
+mod input;
+mod manual;
+mod math;
 mod nerds;
+mod site;
+mod store;
+
+struct AppState {
+    config: config::Config,
+    nerds: nerds::Registry,
+    cache: Arc<cache::InfoCache>,
+    store: Option<store::Store>,
+    manual: manual::LiveLibrary,
+    assets: assets::Assets,
+}
 
 #[tokio::main]
-async fn main() {
-    let app = Router::new()
+async fn main() -> ExitCode {
+    let args = cli::Args::parse();
+    let config = match args.settings.config() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut registry = nerds::Registry::default();
+    config.apply_to(&mut registry);
+
+    let store = match &config.store {
+        Some(path) => match store::Store::open(path) {
+            Ok(store) => Some(store.with_min_duration(config.store_after)),
+            Err(err) => {
+                eprintln!(
+                    "Error: cannot open the fact store {}: {err}",
+                    path.display()
+                );
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+
+    match args.command {
+        Some(cli::Command::Serve) | None => serve(config, registry, store).await,
+        Some(cli::Command::Show { n, format }) => {
+            cli::show(&registry, store.as_ref(), &config, &n, format).await
+        }
+        Some(cli::Command::Build {
+            range,
+            out,
+            base_url,
+        }) => match site::build(
+            &config,
+            &registry,
+            store.as_ref(),
+            range,
+            &out,
+            base_url.as_deref(),
+        )
+        .await
+        {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Error: cannot build the site in {}: {err}", out.display());
+                ExitCode::FAILURE
+            }
+        },
+        Some(cli::Command::Precompute { range }) => {
+            let Some(store) = store else {
+                eprintln!("Error: use --store or NATWIKI_STORE to say where to store the facts");
+                return ExitCode::FAILURE;
+            };
+            match store::precompute(&config, &registry, &store, range).await {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(err) => {
+                    eprintln!("Error: cannot store facts: {err}");
+                    ExitCode::FAILURE
+                }
+            }
+        }
+    }
+}
+
+async fn serve(
+    config: config::Config,
+    registry: nerds::Registry,
+    store: Option<store::Store>,
+) -> ExitCode {
+    let state = Arc::new(AppState {
+        nerds: registry,
+        cache: Arc::new(cache::InfoCache::new(config.cache_capacity)),
+        store,
+        manual: manual::LiveLibrary::open(config.manual_dir.clone(), filters::LinkStyle::Absolute),
+        assets: assets::Assets::new(config.assets_dir.clone()),
+        config,
+    });
+    let bind = state.config.bind.clone();
+    let admin_page = state.config.admin_page;
+
+    let mut app = Router::new()
         .route("/:n", get(handle_int))
-        .route("/random", get(handle_random));
+        .route("/:n/events", get(events::handle_events))
+        .route("/random", get(handle_random))
+        .route("/api/v1/:n", get(api::handle_int))
+        .route("/about/nerds", get(handle_about_nerds))
+        .route("/manual", get(handle_manual_index))
+        .route("/assets/:name", get(assets::handle_asset));
+    if admin_page {
+        app = app.route("/admin/config", get(handle_admin_config));
+    }
+    let app = app.with_state(state);
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let listener = match tokio::net::TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("Error: cannot listen on {bind}: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = axum::serve(listener, app).await {
+        eprintln!("Error: {err}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
 }
 
 #[derive(Template)]
 #[template(path = "int.html")]
 struct IntTemplate {
-    n: Arc<Integer>,
-    manual: Option<String>,
-    info: nerds::NumberInfo,
+    ctx: Arc<nerds::NumberContext>,
+    manual: Option<Arc<manual::Manual>>,
+    /// The hand-written parts about classes of numbers this one belongs to.
+    class_manuals: Vec<Arc<manual::Manual>>,
+    info: Arc<nerds::NumberInfo>,
+    links: filters::LinkStyle,
+    /// What the user originally typed, if it wasn't the number in decimal.
+    redirected_from: Option<String>,
+    read_as: Option<input::Notation>,
+    /// Whether the nerds are still busy, and the page should listen for the rest of their facts.
+    pending: bool,
+    /// How many facts the page already shows.
+    seen: usize,
 }
 
-async fn handle_int(Path(param): Path<String>) -> Result<IntTemplate, (StatusCode, String)> {
-    let Ok(n) = Integer::parse(&param).map(rug::Complete::complete) else {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            format!("Error: {param:?} could not be parsed as a natural number."),
-        ));
-    };
-    if n.is_negative() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            format!("Error: {n} is not a natural number."),
-        ));
+/// What the nerds know about the context's number, asking them in the background if necessary.
+fn analyse(
+    state: &Arc<AppState>,
+    ctx: &Arc<nerds::NumberContext>,
+) -> watch::Receiver<cache::Progress> {
+    let nerds_state = state.clone();
+    let nerds_ctx = ctx.clone();
+    state.cache.analyse(ctx, move |reporter| async move {
+        let report = |fact: &nerds::Fact| reporter.report(fact);
+        let store = nerds_state.store.as_ref();
+        nerds::ask_nerds_and_report(&nerds_state.nerds, nerds_ctx, store, report).await
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct IntQuery {
+    /// What the user typed before being redirected here.
+    from: Option<String>,
+    /// How that was read.
+    #[serde(rename = "as")]
+    read_as: Option<input::Notation>,
+}
+
+async fn handle_int(
+    State(state): State<Arc<AppState>>,
+    Path(param): Path<String>,
+    Query(query): Query<IntQuery>,
+    headers: HeaderMap,
+) -> Response {
+    if api::wants_json(&headers) {
+        return api::handle_int(State(state), Path(param))
+            .await
+            .into_response();
     }
 
-    let n = Arc::new(n);
+    let (n, notation) = match input::parse(&param, state.config.max_digits) {
+        Ok(parsed) => parsed,
+        Err(err) => return (err.status(), format!("Error: {err}")).into_response(),
+    };
+    // Anything which isn't plain decimal gets redirected to the number's canonical page.
+    let digits = n.to_string();
+    if param != digits {
+        let query = IntQuery {
+            from: Some(param),
+            read_as: Some(notation),
+        };
+        // Note: This can't fail, IntQuery only has string fields.
+        let query = serde_urlencoded::to_string(&query).unwrap();
+        return Redirect::to(&format!("/{digits}?{query}")).into_response();
+    }
 
-    let get_manual = tokio::fs::read_to_string(format!("templates/{n}.html"));
-    let (manual, facts) = tokio::join!(get_manual, nerds::ask_nerds(n.clone()));
+    let ctx = state.config.number_context(n);
+    let mut progress = analyse(&state, &ctx);
+    // Give the nerds a moment, so that most pages are sent complete.
+    // Whatever was heard by then is sent either way.
+    let finished = progress.wait_for(|progress| progress.info.is_some());
+    let _ = tokio::time::timeout(state.config.first_paint, finished).await;
 
-    Ok(IntTemplate {
-        n,
-        manual: manual.ok(),
-        info: facts,
-    })
+    let (info, pending, seen) = {
+        let progress = progress.borrow();
+        match &progress.info {
+            Some(info) => (info.clone(), false, progress.facts.len()),
+            None => (
+                Arc::new(nerds::NumberInfo::partial(progress.facts.clone())),
+                true,
+                progress.facts.len(),
+            ),
+        }
+    };
+    let library = state.manual.current();
+    IntTemplate {
+        ctx: ctx.clone(),
+        manual: library.number(ctx.n()),
+        class_manuals: library.classes_of(ctx.n(), &info.classes),
+        info,
+        links: filters::LinkStyle::Absolute,
+        redirected_from: query.from,
+        read_as: query.read_as,
+        pending,
+        seen,
+    }
+    .into_response()
 }
 
-async fn handle_random() -> Redirect {
+async fn handle_random(State(state): State<Arc<AppState>>) -> Redirect {
     let mut rng = rand::thread_rng();
     let mut digits = String::new();
 
     digits.push(rng.gen_range('1'..='9'));
-    while rng.gen_bool(0.75) {
+    while rng.gen_bool(state.config.random_digit_chance) {
         digits.push(rng.gen_range('0'..='9'));
     }
 
     Redirect::to(&format!("/{digits}"))
 }
+
+#[derive(Template)]
+#[template(path = "about_nerds.html")]
+struct AboutNerdsTemplate {
+    state: Arc<AppState>,
+}
+
+async fn handle_about_nerds(State(state): State<Arc<AppState>>) -> AboutNerdsTemplate {
+    AboutNerdsTemplate { state }
+}
+
+#[derive(Template)]
+#[template(path = "manual_index.html")]
+struct ManualIndexTemplate {
+    library: Arc<manual::Library>,
+    links: filters::LinkStyle,
+}
+
+async fn handle_manual_index(State(state): State<Arc<AppState>>) -> ManualIndexTemplate {
+    ManualIndexTemplate {
+        library: state.manual.current(),
+        links: filters::LinkStyle::Absolute,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin_config.html")]
+struct AdminConfigTemplate {
+    state: Arc<AppState>,
+}
+
+async fn handle_admin_config(State(state): State<Arc<AppState>>) -> AdminConfigTemplate {
+    AdminConfigTemplate { state }
+}
@@ -0,0 +1,328 @@
+//! Every manual page, loaded all at once and kept up to date while the server runs.
+//!
+//! The pages in `manual/` are built into the binary. Pages in an override directory
+//! replace the built-in ones with the same path, and are watched for changes.
+//!
+//! Pages about single numbers are named after the number in decimal, like `37.md`.
+//! Pages about classes of numbers are in the `classes` subdirectory, under any name.
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use rug::Integer;
+use rust_embed::RustEmbed;
+use tokio::sync::{mpsc, watch};
+
+use super::{render, render_class, Manual, Rule};
+use crate::{filters::LinkStyle, nerds::Class};
+
+/// Editors often save a file in several steps, so changes are only looked at after they settle.
+const RELOAD_DELAY: Duration = Duration::from_millis(200);
+
+const CLASSES_DIR: &str = "classes";
+const EXTENSION: &str = "md";
+
+#[derive(RustEmbed)]
+#[folder = "manual/"]
+struct Embedded;
+
+/// A manual page about a class of numbers.
+#[derive(Debug)]
+pub struct ClassPage {
+    /// The file name, without the extension.
+    pub name: String,
+    pub rule: Rule,
+    pub manual: Arc<Manual>,
+}
+
+/// Every manual page, rendered.
+#[derive(Debug, Default)]
+pub struct Library {
+    numbers: BTreeMap<Integer, Arc<Manual>>,
+    /// In the order of their file names.
+    classes: Vec<ClassPage>,
+}
+
+/// The text of a page, and where it came from.
+struct Source {
+    origin: String,
+    text: io::Result<String>,
+}
+
+/// The file name without the extension, if it has the extension.
+fn stem(name: &str) -> Option<&str> {
+    name.strip_suffix(EXTENSION)?.strip_suffix('.')
+}
+
+/// The number a page with this file name is about,
+/// if the name is the number in decimal without a sign or leading zeros.
+fn number_from_file_name(name: &str) -> Option<Integer> {
+    let digits = stem(name)?;
+    let n = Integer::from_str_radix(digits, 10).ok()?;
+    (n >= 0 && n.to_string() == digits).then_some(n)
+}
+
+/// The Markdown files in `dir`, by name.
+fn markdown_files(dir: &Path) -> io::Result<BTreeMap<String, PathBuf>> {
+    let mut files = BTreeMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            continue;
+        }
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            eprintln!("Warning: ignoring manual page {}", path.display());
+            continue;
+        };
+        if path.extension().is_some_and(|ext| ext == EXTENSION) {
+            files.insert(name.to_owned(), path);
+        }
+    }
+    Ok(files)
+}
+
+/// Every page by its path in the manual, like `37.md` or `classes/perfect.md`.
+/// Pages in `dir` replace the built-in ones with the same path.
+fn sources(dir: Option<&Path>) -> io::Result<BTreeMap<String, Source>> {
+    let mut sources = BTreeMap::new();
+    for path in Embedded::iter() {
+        // Note: This can't fail, the path was just listed.
+        let file = Embedded::get(&path).unwrap();
+        let text = String::from_utf8(file.data.into_owned())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err));
+        let origin = format!("built-in page {path}");
+        sources.insert(path.into_owned(), Source { origin, text });
+    }
+
+    let Some(dir) = dir else {
+        return Ok(sources);
+    };
+    let classes = dir.join(CLASSES_DIR);
+    let mut files = markdown_files(dir)?;
+    if classes.is_dir() {
+        for (name, path) in markdown_files(&classes)? {
+            files.insert(format!("{CLASSES_DIR}/{name}"), path);
+        }
+    }
+    for (name, path) in files {
+        let source = Source {
+            origin: path.display().to_string(),
+            text: fs::read_to_string(&path),
+        };
+        sources.insert(name, source);
+    }
+    Ok(sources)
+}
+
+impl Library {
+    /// Reads and renders every page, overridden by those in `dir` if there is one,
+    /// with links to numbers in the given style.
+    ///
+    /// Pages which can't be read, rendered, or told what they are about are left out with a warning.
+    pub fn load(dir: Option<&Path>, links: &LinkStyle) -> io::Result<Self> {
+        let mut library = Library::default();
+        for (path, Source { origin, text }) in sources(dir)? {
+            let text = match text {
+                Ok(text) => text,
+                Err(err) => {
+                    eprintln!("Warning: cannot read {origin}: {err}");
+                    continue;
+                }
+            };
+            let rendered = if let Some(name) = path.strip_prefix(&format!("{CLASSES_DIR}/")) {
+                render_class(&text, links).map(|(rule, manual)| {
+                    library.classes.push(ClassPage {
+                        name: stem(name).unwrap_or(name).to_owned(),
+                        rule,
+                        manual: Arc::new(manual),
+                    });
+                })
+            } else if let Some(n) = number_from_file_name(&path) {
+                render(&text, links).map(|manual| {
+                    library.numbers.insert(n, Arc::new(manual));
+                })
+            } else {
+                eprintln!(
+                    "Warning: ignoring {origin}, which isn't named after a number like 37.{EXTENSION}"
+                );
+                continue;
+            };
+            if let Err(err) = rendered {
+                eprintln!("Warning: cannot render {origin}: {err}");
+            }
+        }
+        Ok(library)
+    }
+
+    /// The page about `n`, if there is one.
+    pub fn number(&self, n: &Integer) -> Option<Arc<Manual>> {
+        self.numbers.get(n).cloned()
+    }
+
+    /// The pages about classes of numbers which `n`, belonging to `classes`, is in.
+    pub fn classes_of(&self, n: &Integer, classes: &[Class]) -> Vec<Arc<Manual>> {
+        self.classes
+            .iter()
+            .filter(|page| page.rule.matches(n, classes))
+            .map(|page| page.manual.clone())
+            .collect()
+    }
+
+    /// Every number with a page, in order.
+    pub fn numbers(&self) -> impl Iterator<Item = (&Integer, &Manual)> {
+        self.numbers.iter().map(|(n, manual)| (n, &**manual))
+    }
+
+    pub fn classes(&self) -> &[ClassPage] {
+        &self.classes
+    }
+}
+
+/// The library, which is loaded again whenever something in its override directory changes.
+pub struct LiveLibrary {
+    library: watch::Receiver<Arc<Library>>,
+    /// Reloading stops once this is dropped.
+    _watcher: Option<RecommendedWatcher>,
+}
+
+impl LiveLibrary {
+    /// Loads the library and starts watching `dir` if there is one.
+    /// Must be called within a Tokio runtime.
+    ///
+    /// If the directory can't be read or watched, this says why and carries on with what it has.
+    pub fn open(dir: Option<PathBuf>, links: LinkStyle) -> Self {
+        let library = match Library::load(dir.as_deref(), &links) {
+            Ok(library) => library,
+            Err(err) => {
+                eprintln!("Warning: cannot read the manual overrides: {err}");
+                // Note: This can't fail, only reading the directory can.
+                Library::load(None, &links).unwrap()
+            }
+        };
+        let (tx, library) = watch::channel(Arc::new(library));
+        let watcher = dir.and_then(|dir| match watch_dir(dir.clone(), links, tx) {
+            Ok(watcher) => Some(watcher),
+            Err(err) => {
+                eprintln!(
+                    "Warning: cannot watch the manual in {} for changes: {err}",
+                    dir.display()
+                );
+                None
+            }
+        });
+        Self {
+            library,
+            _watcher: watcher,
+        }
+    }
+
+    pub fn current(&self) -> Arc<Library> {
+        self.library.borrow().clone()
+    }
+}
+
+fn watch_dir(
+    dir: PathBuf,
+    links: LinkStyle,
+    tx: watch::Sender<Arc<Library>>,
+) -> notify::Result<RecommendedWatcher> {
+    let (changed, mut changes) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        // Reading the pages shouldn't make them be read again.
+        if event.is_ok_and(|event| !matches!(event.kind, EventKind::Access(_))) {
+            let _ = changed.send(());
+        }
+    })?;
+    watcher.watch(&dir, RecursiveMode::Recursive)?;
+
+    // Ends when the watcher is dropped, along with the sender it owns.
+    tokio::spawn(async move {
+        while changes.recv().await.is_some() {
+            tokio::time::sleep(RELOAD_DELAY).await;
+            while changes.try_recv().is_ok() {}
+
+            let dir = dir.clone();
+            let links = links.clone();
+            let loaded =
+                tokio::task::spawn_blocking(move || Library::load(Some(&dir), &links)).await;
+            match loaded {
+                Ok(Ok(library)) => {
+                    eprintln!(
+                        "Reloaded the manual: {} numbers and {} classes",
+                        library.numbers.len(),
+                        library.classes.len()
+                    );
+                    tx.send_replace(Arc::new(library));
+                }
+                // Keep the old pages until the directory can be read again.
+                Ok(Err(err)) => eprintln!("Warning: cannot reload the manual: {err}"),
+                Err(err) => std::panic::resume_unwind(err.into_panic()),
+            }
+        }
+    });
+    Ok(watcher)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names_are_canonical() {
+        assert_eq!(number_from_file_name("37.md"), Some(Integer::from(37)));
+        assert_eq!(number_from_file_name("0.md"), Some(Integer::from(0)));
+        for name in [
+            "037.md", "+37.md", "-1.md", "37.html", "x.md", ".md", "37md", " 37.md",
+        ] {
+            assert_eq!(number_from_file_name(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn built_in_pages() {
+        let library = Library::load(None, &LinkStyle::Absolute).unwrap();
+        assert!(library.number(&Integer::from(37)).is_some());
+        assert!(library.classes().iter().any(|page| page.name == "perfect"));
+
+        let two_squares = |n: u32| {
+            library
+                .classes_of(&Integer::from(n), &[Class::Prime])
+                .iter()
+                .any(|page| page.title.as_deref() == Some("Primes which are sums of two squares"))
+        };
+        assert!(two_squares(2));
+        assert!(two_squares(13));
+        assert!(!two_squares(3));
+    }
+
+    #[test]
+    fn overrides_built_in_pages() {
+        let dir = std::env::temp_dir().join(format!("natwiki-manual-{}", std::process::id()));
+        fs::create_dir_all(dir.join(CLASSES_DIR)).unwrap();
+        fs::write(dir.join("37.md"), "+++\ntitle = \"Thirty-seven\"\n+++\n").unwrap();
+        fs::write(dir.join("037.md"), "Not canonical.").unwrap();
+        fs::write(dir.join("4.md"), "+++\nrule = \"even\"\n+++\n").unwrap();
+        fs::write(dir.join("5.md"), "Five.").unwrap();
+        let odd = dir.join(CLASSES_DIR).join("odd.md");
+        fs::write(odd, "+++\ntitle = \"Odd\"\nrule = \"odd\"\n+++\n").unwrap();
+
+        let library = Library::load(Some(&dir), &LinkStyle::Absolute);
+        fs::remove_dir_all(&dir).unwrap();
+        let library = library.unwrap();
+
+        let title = library.number(&Integer::from(37)).unwrap().title.clone();
+        assert_eq!(title.as_deref(), Some("Thirty-seven"));
+        assert!(library.number(&Integer::from(5)).is_some());
+        // Not canonical, or not about a single number.
+        assert!(library.number(&Integer::from(4)).is_none());
+
+        let odd = library.classes_of(&Integer::from(9), &[Class::Odd]);
+        assert_eq!(odd.len(), 1);
+        assert_eq!(odd[0].title.as_deref(), Some("Odd"));
+    }
+}
@@ -0,0 +1,298 @@
+//! Hand-written content about numbers, in Markdown.
+//!
+//! A manual page may start with front matter in TOML between `+++` lines:
+//!
+//! ```text
+//! +++
+//! title = "The loneliest number"
+//! tags = ["unit"]
+//! sources = ["https://oeis.org/A000012"]
+//! +++
+//! ```
+//!
+//! Pages about a class of numbers rather than a single one have a `rule` in their front matter,
+//! see the `rule` module.
+//!
+//! Text is passed through `mathfmt`, so `(#k)` links to other numbers and `(^...)` is a superscript.
+//! Raw HTML is shown as text rather than inserted into the page.
+use std::fmt;
+
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd, TextMergeStream};
+use serde::Deserialize;
+
+use crate::filters::{self, LinkStyle};
+
+mod library;
+mod rule;
+
+pub use library::{Library, LiveLibrary};
+pub use rule::{Rule, RuleError};
+
+const FRONT_MATTER_DELIMITER: &str = "+++";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FrontMatter {
+    title: Option<String>,
+    rule: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    #[serde(default)]
+    sources: Vec<String>,
+}
+
+/// A rendered manual page.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Manual {
+    /// In mathfmt markup.
+    pub title: Option<String>,
+    pub tags: Vec<String>,
+    /// URLs or citations.
+    pub sources: Vec<String>,
+    /// The body, rendered to sanitised HTML.
+    pub html: String,
+}
+
+#[derive(Debug)]
+pub enum ManualError {
+    /// The front matter was started, but never ended.
+    UnclosedFrontMatter,
+    FrontMatter(toml::de::Error),
+    /// Only class pages have rules.
+    UnexpectedRule,
+    MissingRule,
+    Rule(RuleError),
+}
+
+impl fmt::Display for ManualError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManualError::UnclosedFrontMatter => {
+                write!(
+                    f,
+                    "the front matter has no closing {FRONT_MATTER_DELIMITER:?}"
+                )
+            }
+            ManualError::FrontMatter(err) => write!(f, "invalid front matter: {err}"),
+            ManualError::UnexpectedRule => {
+                f.write_str("only pages about classes of numbers can have a rule")
+            }
+            ManualError::MissingRule => {
+                f.write_str("pages about classes of numbers need a rule saying which numbers")
+            }
+            ManualError::Rule(err) => write!(f, "invalid rule: {err}"),
+        }
+    }
+}
+
+/// Splits the source into its front matter, if it has any, and its body.
+fn split_front_matter(source: &str) -> Result<(Option<&str>, &str), ManualError> {
+    let Some(rest) = source
+        .strip_prefix(FRONT_MATTER_DELIMITER)
+        .and_then(|rest| rest.strip_prefix('\n').or(rest.strip_prefix("\r\n")))
+    else {
+        return Ok((None, source));
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == FRONT_MATTER_DELIMITER {
+            return Ok((Some(&rest[..offset]), &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    Err(ManualError::UnclosedFrontMatter)
+}
+
+/// Whether following the link can't run scripts, i.e. it is relative or has a harmless scheme.
+fn is_safe_url(url: &str) -> bool {
+    match url.split_once(':') {
+        // A colon after the start of the path, query or fragment doesn't start a scheme.
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => ["http", "https", "mailto"]
+            .iter()
+            .any(|safe| scheme.eq_ignore_ascii_case(safe)),
+        _ => true,
+    }
+}
+
+fn sanitise_url(url: CowStr<'_>) -> CowStr<'_> {
+    if is_safe_url(&url) {
+        url
+    } else {
+        CowStr::Borrowed("#")
+    }
+}
+
+/// Renders a manual page about a single number, with links to numbers in the given style.
+pub fn render(source: &str, links: &LinkStyle) -> Result<Manual, ManualError> {
+    match render_with_rule(source, links)? {
+        (None, manual) => Ok(manual),
+        (Some(_), _) => Err(ManualError::UnexpectedRule),
+    }
+}
+
+/// Renders a manual page about a class of numbers, along with the rule for which numbers.
+pub fn render_class(source: &str, links: &LinkStyle) -> Result<(Rule, Manual), ManualError> {
+    match render_with_rule(source, links)? {
+        (Some(rule), manual) => Ok((rule.parse().map_err(ManualError::Rule)?, manual)),
+        (None, _) => Err(ManualError::MissingRule),
+    }
+}
+
+fn render_with_rule(
+    source: &str,
+    links: &LinkStyle,
+) -> Result<(Option<String>, Manual), ManualError> {
+    let (front_matter, body) = split_front_matter(source)?;
+    let front_matter: FrontMatter = match front_matter {
+        Some(toml) => toml::from_str(toml).map_err(ManualError::FrontMatter)?,
+        None => FrontMatter::default(),
+    };
+
+    let mut in_code_block = false;
+    let events = TextMergeStream::new(Parser::new_ext(body, Options::ENABLE_TABLES)).map(|event| {
+        match event {
+            Event::Start(Tag::CodeBlock(kind)) => {
+                in_code_block = true;
+                Event::Start(Tag::CodeBlock(kind))
+            }
+            Event::End(TagEnd::CodeBlock) => {
+                in_code_block = false;
+                Event::End(TagEnd::CodeBlock)
+            }
+            Event::Text(text) if !in_code_block => {
+                // Note: This can't fail, mathfmt always returns Ok.
+                Event::InlineHtml(filters::mathfmt(&text, links).unwrap().into())
+            }
+            Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+            Event::Start(Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Link {
+                link_type,
+                dest_url: sanitise_url(dest_url),
+                title,
+                id,
+            }),
+            Event::Start(Tag::Image {
+                link_type,
+                dest_url,
+                title,
+                id,
+            }) => Event::Start(Tag::Image {
+                link_type,
+                dest_url: sanitise_url(dest_url),
+                title,
+                id,
+            }),
+            event => event,
+        }
+    });
+    let mut html = String::new();
+    html::push_html(&mut html, events);
+
+    let manual = Manual {
+        title: front_matter.title,
+        tags: front_matter.tags,
+        sources: front_matter.sources,
+        html,
+    };
+    Ok((front_matter.rule, manual))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_html(source: &str) -> String {
+        render(source, &LinkStyle::Absolute).unwrap().html
+    }
+
+    #[test]
+    fn reads_front_matter() {
+        let manual = render(
+            "+++\ntitle = \"(#2)(^(#2))\"\ntags = [\"square\"]\n+++\nFour.\n",
+            &LinkStyle::Absolute,
+        )
+        .unwrap();
+        assert_eq!(manual.title.as_deref(), Some("(#2)(^(#2))"));
+        assert_eq!(manual.tags, vec!["square"]);
+        assert!(manual.sources.is_empty());
+        assert_eq!(manual.html, "<p>Four.</p>\n");
+
+        assert_eq!(
+            render_html("No front matter.\n+++\n"),
+            "<p>No front matter.\n+++</p>\n"
+        );
+        assert!(matches!(
+            render("+++\ntitle = \"Four\"\n", &LinkStyle::Absolute),
+            Err(ManualError::UnclosedFrontMatter)
+        ));
+        assert!(matches!(
+            render("+++\nauthor = \"Me\"\n+++\n", &LinkStyle::Absolute),
+            Err(ManualError::FrontMatter(_))
+        ));
+    }
+
+    #[test]
+    fn class_pages_have_rules() {
+        let source = "+++\nrule = \"prime and n % 4 == 1\"\n+++\nA sum of two squares.\n";
+        let (rule, manual) = render_class(source, &LinkStyle::Absolute).unwrap();
+        assert_eq!(rule, "prime and n % 4 == 1".parse().unwrap());
+        assert_eq!(manual.html, "<p>A sum of two squares.</p>\n");
+        assert!(matches!(
+            render(source, &LinkStyle::Absolute),
+            Err(ManualError::UnexpectedRule)
+        ));
+
+        assert!(matches!(
+            render_class("Primes.", &LinkStyle::Absolute),
+            Err(ManualError::MissingRule)
+        ));
+        assert!(matches!(
+            render_class("+++\nrule = \"primes\"\n+++\n", &LinkStyle::Absolute),
+            Err(ManualError::Rule(RuleError::UnknownClass(_)))
+        ));
+    }
+
+    #[test]
+    fn formats_math() {
+        assert_eq!(
+            render_html("- Is (#2)(^(#5))-(#1).\n"),
+            "<ul>\n<li>Is <a href=\"/2\">2</a><sup><a href=\"/5\">5</a></sup>-<a href=\"/1\">1</a>.</li>\n</ul>\n"
+        );
+        assert_eq!(
+            render("See (#10).", &LinkStyle::Static(0..=5))
+                .unwrap()
+                .html,
+            "<p>See <span class=\"out-of-range\">10</span>.</p>\n"
+        );
+        // Code is shown as written.
+        assert_eq!(render_html("`(#2)`"), "<p><code>(#2)</code></p>\n");
+        assert_eq!(
+            render_html("```\n(#2)\n```\n"),
+            "<pre><code>(#2)\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn sanitises() {
+        assert_eq!(
+            render_html("<script>alert(1)</script>\n"),
+            "&lt;script&gt;alert(1)&lt;/script&gt;\n"
+        );
+        assert_eq!(
+            render_html("A <b>bold</b> claim & more."),
+            "<p>A &lt;b&gt;bold&lt;/b&gt; claim &amp; more.</p>\n"
+        );
+        assert_eq!(
+            render_html("[click](javascript:alert(1))"),
+            "<p><a href=\"#\">click</a></p>\n"
+        );
+        assert_eq!(
+            render_html("[OEIS](https://oeis.org/A000045) [two](/2) [x](a/b:c)"),
+            "<p><a href=\"https://oeis.org/A000045\">OEIS</a> <a href=\"/2\">two</a> <a href=\"a/b:c\">x</a></p>\n"
+        );
+    }
+}
@@ -0,0 +1,314 @@
+//! Rules saying which numbers a class page of the manual is about.
+//!
+//! A rule combines classes the nerds know about, like `prime` or `perfect`,
+//! with conditions on the number itself, like `n % 4 == 1` or `n < 1000`,
+//! using `and`, `or`, `not` and parentheses. For example `prime and n % 4 == 1`.
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use rug::Integer;
+
+use crate::nerds::Class;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RuleError {
+    /// `pos` is the number of characters before the problem.
+    Syntax {
+        pos: usize,
+        expected: &'static str,
+    },
+    UnknownClass(String),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RuleError::Syntax { pos, expected } => {
+                write!(f, "expected {expected} at position {pos}")
+            }
+            RuleError::UnknownClass(name) => write!(f, "there is no class called {name:?}"),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, RuleError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    Class(Class),
+    /// `n op value`, or `n % modulus op value` if there is a modulus.
+    Compare {
+        modulus: Option<Integer>,
+        op: Ordering,
+        negated: bool,
+        value: Integer,
+    },
+    Not(Box<Rule>),
+    And(Box<Rule>, Box<Rule>),
+    Or(Box<Rule>, Box<Rule>),
+}
+
+impl Rule {
+    /// Whether the number `n`, which belongs to `classes`, follows the rule.
+    pub fn matches(&self, n: &Integer, classes: &[Class]) -> bool {
+        match self {
+            Rule::Class(class) => classes.contains(class),
+            Rule::Compare {
+                modulus,
+                op,
+                negated,
+                value,
+            } => {
+                let ordering = match modulus {
+                    Some(modulus) => Integer::from(n % modulus).cmp(value),
+                    None => n.cmp(value),
+                };
+                (ordering == *op) != *negated
+            }
+            Rule::Not(rule) => !rule.matches(n, classes),
+            Rule::And(a, b) => a.matches(n, classes) && b.matches(n, classes),
+            Rule::Or(a, b) => a.matches(n, classes) || b.matches(n, classes),
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = RuleError;
+
+    fn from_str(src: &str) -> Result<Rule> {
+        let mut parser = Parser { src, pos: 0 };
+        let rule = parser.or()?;
+        parser.skip_whitespace();
+        if parser.pos < src.len() {
+            return Err(parser.expected("`and`, `or` or the end of the rule"));
+        }
+        Ok(rule)
+    }
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    /// Byte offset of the next unparsed character.
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.src[self.pos..].chars().next() {
+            if !c.is_whitespace() {
+                break;
+            }
+            self.pos += c.len_utf8();
+        }
+    }
+
+    fn rest(&mut self) -> &str {
+        self.skip_whitespace();
+        &self.src[self.pos..]
+    }
+
+    /// Consumes `token` if it is next.
+    fn eat(&mut self, token: &str) -> bool {
+        let found = self.rest().starts_with(token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    /// The next word, made of letters, digits and hyphens, without consuming it.
+    fn peek_word(&mut self) -> &str {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+            .unwrap_or(rest.len());
+        &rest[..len]
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_word() == keyword;
+        if found {
+            self.pos += keyword.len();
+        }
+        found
+    }
+
+    fn expected(&self, expected: &'static str) -> RuleError {
+        RuleError::Syntax {
+            pos: self.src[..self.pos].chars().count(),
+            expected,
+        }
+    }
+
+    fn or(&mut self) -> Result<Rule> {
+        let mut rule = self.and()?;
+        while self.eat_keyword("or") {
+            rule = Rule::Or(Box::new(rule), Box::new(self.and()?));
+        }
+        Ok(rule)
+    }
+
+    fn and(&mut self) -> Result<Rule> {
+        let mut rule = self.not()?;
+        while self.eat_keyword("and") {
+            rule = Rule::And(Box::new(rule), Box::new(self.not()?));
+        }
+        Ok(rule)
+    }
+
+    fn not(&mut self) -> Result<Rule> {
+        if self.eat_keyword("not") {
+            Ok(Rule::Not(Box::new(self.not()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn atom(&mut self) -> Result<Rule> {
+        if self.eat("(") {
+            let rule = self.or()?;
+            if !self.eat(")") {
+                return Err(self.expected("`)`"));
+            }
+            return Ok(rule);
+        }
+        if self.eat_keyword("n") {
+            return self.comparison();
+        }
+        let name = self.peek_word().to_owned();
+        if name.is_empty() {
+            return Err(self.expected("a class, `n`, `not` or `(`"));
+        }
+        let class = Class::from_name(&name).ok_or(RuleError::UnknownClass(name.clone()))?;
+        self.pos += name.len();
+        Ok(Rule::Class(class))
+    }
+
+    /// The rest of a comparison, after the `n`.
+    fn comparison(&mut self) -> Result<Rule> {
+        let modulus = if self.eat("%") {
+            let modulus = self.number()?;
+            if modulus.is_zero() {
+                return Err(self.expected("a nonzero modulus"));
+            }
+            Some(modulus)
+        } else {
+            None
+        };
+        // Longer operators first, so `<=` isn't read as `<`.
+        let (op, negated) = [
+            ("==", Ordering::Equal, false),
+            ("!=", Ordering::Equal, true),
+            ("<=", Ordering::Greater, true),
+            (">=", Ordering::Less, true),
+            ("<", Ordering::Less, false),
+            (">", Ordering::Greater, false),
+        ]
+        .into_iter()
+        .find(|(token, ..)| self.eat(token))
+        .map(|(_, op, negated)| (op, negated))
+        .ok_or_else(|| self.expected("a comparison"))?;
+        Ok(Rule::Compare {
+            modulus,
+            op,
+            negated,
+            value: self.number()?,
+        })
+    }
+
+    fn number(&mut self) -> Result<Integer> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.expected("a number"));
+        }
+        // Note: This can't fail, the digits were just checked.
+        let n = rest[..len].parse().unwrap();
+        self.pos += len;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(rule: &str, n: u32, classes: &[Class]) -> bool {
+        rule.parse::<Rule>()
+            .unwrap()
+            .matches(&Integer::from(n), classes)
+    }
+
+    #[test]
+    fn evaluates() {
+        assert!(matches("prime", 5, &[Class::Odd, Class::Prime]));
+        assert!(!matches("perfect", 5, &[Class::Odd, Class::Prime]));
+        assert!(matches("prime and n % 4 == 1", 5, &[Class::Prime]));
+        assert!(!matches("prime and n % 4 == 1", 7, &[Class::Prime]));
+        assert!(matches("n%4==3", 7, &[]));
+        assert!(matches("not prime or n < 10", 7, &[Class::Prime]));
+        assert!(!matches("not (prime or n < 10)", 12, &[Class::Prime]));
+        assert!(matches("n <= 12 and n >= 12 and n != 13", 12, &[]));
+        assert!(!matches("n > 12", 12, &[]));
+        assert!(matches(
+            "mersenne-prime or almost-perfect",
+            16,
+            &[Class::AlmostPerfect]
+        ));
+        // `and` binds tighter than `or`.
+        assert!(matches("even or odd and prime", 4, &[Class::Even]));
+        assert!(matches("n % 1000000000000000000000 == 3", 3, &[]));
+        // Non-breaking spaces are more than one byte long.
+        assert!(matches(
+            "prime\u{a0}and\u{a0}n\u{a0}<\u{a0}10",
+            7,
+            &[Class::Prime]
+        ));
+    }
+
+    #[test]
+    fn errors() {
+        let err = |rule: &str| rule.parse::<Rule>().unwrap_err();
+        assert_eq!(err("boring"), RuleError::UnknownClass("boring".to_owned()));
+        assert_eq!(
+            err("prime and"),
+            RuleError::Syntax {
+                pos: 9,
+                expected: "a class, `n`, `not` or `(`"
+            }
+        );
+        assert_eq!(
+            err("n % 0 == 1"),
+            RuleError::Syntax {
+                pos: 5,
+                expected: "a nonzero modulus"
+            }
+        );
+        assert_eq!(
+            err("n = 1"),
+            RuleError::Syntax {
+                pos: 2,
+                expected: "a comparison"
+            }
+        );
+        assert_eq!(
+            err("(prime"),
+            RuleError::Syntax {
+                pos: 6,
+                expected: "`)`"
+            }
+        );
+        assert_eq!(
+            err("prime odd"),
+            RuleError::Syntax {
+                pos: 6,
+                expected: "`and`, `or` or the end of the rule"
+            }
+        );
+        assert_eq!(
+            err("mersenne-primes").to_string(),
+            "there is no class called \"mersenne-primes\""
+        );
+    }
+}
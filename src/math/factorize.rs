@@ -0,0 +1,530 @@
+//! Factorization of arbitrarily large integers.
+//!
+//! Factors are searched for in three stages, from cheapest to most expensive:
+//! 1. Trial division by every prime below `TRIAL_LIMIT`.
+//! 2. Pollard's rho with Brent's cycle detection, which is quick to find factors
+//!    of up to about ten digits.
+//! 3. Lenstra's elliptic curve method (ECM), which keeps trying new curves with growing
+//!    bounds. Its running time depends on the size of the smallest factor rather than
+//!    on the size of the number, so it can still peel small-ish factors off huge numbers.
+//!
+//! ECM never gives up by itself, so the search is bounded by a `Budget`.
+//! Any part of the number which is still composite once the budget runs out
+//! is reported as a cofactor.
+use std::sync::OnceLock;
+
+use rug::{integer::IsPrime, ops::Pow, Assign, Complete, Integer};
+
+use super::Budget;
+
+/// Trial division is used for every prime below this.
+const TRIAL_LIMIT: u32 = 1 << 12;
+/// Primes below this are kept around for trial division and ECM stage 1.
+const SIEVE_LIMIT: u32 = 1 << 20;
+/// Pollard's rho is abandoned in favour of ECM after roughly this many iterations.
+const RHO_ITERATIONS: u64 = 1 << 16;
+/// Stage 1 bound and number of curves for each ECM level.
+/// These follow the usual GMP-ECM recommendations for factors of 15, 20, 25, 30 and 35 digits.
+/// The last level is repeated until the budget runs out.
+const ECM_LEVELS: &[(u64, u32)] = &[
+    (2_000, 25),
+    (11_000, 90),
+    (50_000, 300),
+    (250_000, 700),
+    (1_000_000, 1_800),
+];
+/// Stage 2 bound relative to the stage 1 bound.
+const ECM_B2_RATIO: u64 = 100;
+/// Giant step size for ECM stage 2.
+const ECM_D: u64 = 210;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Factorization {
+    /// Prime factors and their multiplicities, in ascending order.
+    pub primes: Vec<(Integer, u32)>,
+    /// The product of every part of the number that could not be split in time.
+    /// Always composite if present.
+    pub cofactor: Option<Integer>,
+}
+
+/// The primes below `limit`, using the sieve of Eratosthenes.
+pub fn sieve(limit: u32) -> Vec<u32> {
+    let mut composite = vec![false; limit as usize];
+    let mut primes = Vec::new();
+    for i in 2..limit as usize {
+        if composite[i] {
+            continue;
+        }
+        primes.push(i as u32);
+        for j in (i * i..limit as usize).step_by(i) {
+            composite[j] = true;
+        }
+    }
+    primes
+}
+
+/// The primes below `SIEVE_LIMIT`, generated on first use.
+pub fn small_primes() -> &'static [u32] {
+    static PRIMES: OnceLock<Vec<u32>> = OnceLock::new();
+    PRIMES.get_or_init(|| sieve(SIEVE_LIMIT))
+}
+
+/// Whether `n` is a prime, or at least probably one after `rounds` Miller-Rabin rounds.
+pub fn is_prime(n: &Integer, rounds: u32) -> bool {
+    *n > 1 && n.is_probably_prime(rounds) != IsPrime::No
+}
+
+/// Factors `n` into primes, using `rounds` Miller-Rabin rounds to decide if a factor is prime.
+/// Returns an empty factorization for n <= 1.
+///
+/// With an unlimited budget this only returns once `n` is completely factored,
+/// which may take a very long time for numbers with several large prime factors.
+pub fn factorize(n: &Integer, rounds: u32, budget: &Budget) -> Factorization {
+    let mut primes: Vec<(Integer, u32)> = Vec::new();
+    if *n <= 1 {
+        return Factorization::default();
+    }
+
+    let mut rest = n.clone();
+    for &p in small_primes().iter().take_while(|&&p| p < TRIAL_LIMIT) {
+        if rest.is_divisible_u(p) {
+            let mut count = 0;
+            while rest.is_divisible_u(p) {
+                rest.div_exact_u_mut(p);
+                count += 1;
+            }
+            primes.push((Integer::from(p), count));
+        }
+    }
+
+    // Every part we split off from here on only has prime factors >= TRIAL_LIMIT,
+    // so anything smaller than TRIAL_LIMIT^2 must be prime.
+    let prime_below = Integer::from(TRIAL_LIMIT).square();
+    // Parts still to be split, along with how many times they divide `n`.
+    let mut pending = vec![(rest, 1)];
+    let composites = loop {
+        let mut composites: Vec<(Integer, u32)> = Vec::new();
+        while let Some((mut m, multiplicity)) = pending.pop() {
+            for (p, count) in primes.iter_mut() {
+                *count += m.remove_factor_mut(p) * multiplicity;
+            }
+            if m == 1 {
+                continue;
+            }
+            // Perfect powers are never prime, and checking for them first
+            // keeps us from running expensive primality tests on huge powers.
+            if let Some((root, k)) = perfect_power_root(&m) {
+                pending.push((root, multiplicity * k));
+                continue;
+            }
+            if m < prime_below || is_prime(&m, rounds) {
+                primes.push((m, multiplicity));
+                continue;
+            }
+            match find_factor(&m, budget) {
+                Some(d) => {
+                    pending.push((m.div_exact_ref(&d).complete(), multiplicity));
+                    pending.push((d, multiplicity));
+                }
+                None => composites.push((m, multiplicity)),
+            }
+        }
+
+        // A part that was given up on may still contain primes which were only found
+        // later while splitting other parts. Those have to be divided out, otherwise
+        // the multiplicities of the primes we report would be wrong.
+        let overlaps = composites
+            .iter()
+            .any(|(c, _)| primes.iter().any(|(p, _)| c.is_divisible(p)));
+        if !overlaps {
+            break composites;
+        }
+        pending = composites;
+    };
+
+    primes.sort();
+    Factorization {
+        primes,
+        cofactor: (!composites.is_empty()).then(|| {
+            composites
+                .into_iter()
+                .map(|(c, multiplicity)| c.pow(multiplicity))
+                .product()
+        }),
+    }
+}
+
+/// Returns (x, k) with x^k = m and k > 1 prime, if such a pair exists.
+/// Expects every prime factor of `m` to be at least `TRIAL_LIMIT`,
+/// which bounds how large `k` can be.
+fn perfect_power_root(m: &Integer) -> Option<(Integer, u32)> {
+    if !m.is_perfect_power() {
+        return None;
+    }
+    let max_exponent = m.significant_bits() / TRIAL_LIMIT.ilog2();
+    small_primes()
+        .iter()
+        .take_while(|&&k| k <= max_exponent)
+        .find_map(|&k| {
+            let (root, rem) = m.root_rem_ref(k).complete();
+            rem.is_zero().then_some((root, k))
+        })
+}
+
+/// Finds a nontrivial (but not necessarily prime) factor of the composite `m`.
+fn find_factor(m: &Integer, budget: &Budget) -> Option<Integer> {
+    for c in 1..=3 {
+        if let Some(d) = pollard_brent(m, c, budget) {
+            return Some(d);
+        }
+    }
+    ecm(m, budget)
+}
+
+/// Pollard's rho using the polynomial x^2 + c, with Brent's cycle detection
+/// and the gcds batched together.
+fn pollard_brent(n: &Integer, c: u32, budget: &Budget) -> Option<Integer> {
+    const BATCH: u64 = 128;
+
+    let step = |y: &mut Integer| {
+        y.square_mut();
+        *y += c;
+        y.modulo_mut(n);
+    };
+
+    let mut x = Integer::new();
+    let mut y = Integer::from(2);
+    let mut ys = Integer::new();
+    let mut q = Integer::from(1);
+    let mut g = Integer::from(1);
+    let mut diff = Integer::new();
+    let mut r: u64 = 1;
+    while g == 1 {
+        x.assign(&y);
+        for _ in 0..r {
+            step(&mut y);
+        }
+        let mut k = 0;
+        while k < r && g == 1 {
+            ys.assign(&y);
+            for _ in 0..BATCH.min(r - k) {
+                step(&mut y);
+                diff.assign(&x - &y);
+                diff.abs_mut();
+                q *= &diff;
+                q.modulo_mut(n);
+            }
+            g.assign(q.gcd_ref(n));
+            k += BATCH;
+        }
+        r *= 2;
+        if g == 1 && (r > RHO_ITERATIONS || budget.is_exhausted()) {
+            return None;
+        }
+    }
+
+    if g == *n {
+        // The batch overshot and collected every factor at once,
+        // so redo it one step at a time.
+        loop {
+            step(&mut ys);
+            diff.assign(&x - &ys);
+            diff.abs_mut();
+            g.assign(diff.gcd_ref(n));
+            if g != 1 {
+                break;
+            }
+        }
+    }
+    (g != *n).then_some(g)
+}
+
+/// A point on a Montgomery curve in projective (X : Z) coordinates.
+#[derive(Debug, Clone)]
+struct Point {
+    x: Integer,
+    z: Integer,
+}
+
+/// The Montgomery curve By^2 = x^3 + Ax^2 + x over Z/nZ.
+/// Only (A+2)/4 is needed for x-only arithmetic.
+struct Curve<'a> {
+    n: &'a Integer,
+    a24: Integer,
+}
+
+impl<'a> Curve<'a> {
+    /// Builds a curve and a point on it using Suyama's parametrization,
+    /// which guarantees a group order divisible by 12.
+    /// If this stumbles upon a factor of `n`, that is returned instead.
+    fn suyama(n: &'a Integer, sigma: u64) -> Result<(Self, Point), Option<Integer>> {
+        let u = (Integer::from(sigma).square() - 5_u8).modulo(n);
+        let v = Integer::from(sigma) * 4_u8;
+        let x = u.clone().pow_mod(&Integer::from(3), n).unwrap();
+        let z = v.clone().pow_mod(&Integer::from(3), n).unwrap();
+
+        let numerator = ((&v - &u).complete().pow_mod(&Integer::from(3), n).unwrap()
+            * (&u * 3_u8 + &v).complete())
+        .modulo(n);
+        let denominator = (&x * &v).complete() * 16_u8;
+        let inverse = match denominator.invert(n) {
+            Ok(inverse) => inverse,
+            Err(denominator) => {
+                let g = denominator.gcd(n);
+                return Err((g != 1 && g != *n).then_some(g));
+            }
+        };
+        let a24 = (numerator * inverse).modulo(n);
+        Ok((Curve { n, a24 }, Point { x, z }))
+    }
+
+    fn double(&self, p: &Point) -> Point {
+        let sum = (&p.x + &p.z).complete().square().modulo(self.n);
+        let diff = (&p.x - &p.z).complete().square().modulo(self.n);
+        let cross = (&sum - &diff).complete();
+        let x = (&sum * &diff).complete().modulo(self.n);
+        let z = (&cross * (diff + &self.a24 * &cross).modulo(self.n)).modulo(self.n);
+        Point { x, z }
+    }
+
+    /// Computes p + q given p - q.
+    fn add(&self, p: &Point, q: &Point, diff: &Point) -> Point {
+        let u = (&p.x - &p.z).complete() * (&q.x + &q.z).complete();
+        let v = (&p.x + &p.z).complete() * (&q.x - &q.z).complete();
+        let sum = (&u + &v).complete().square().modulo(self.n);
+        let dif = (u - v).square().modulo(self.n);
+        Point {
+            x: (&diff.z * sum).modulo(self.n),
+            z: (&diff.x * dif).modulo(self.n),
+        }
+    }
+
+    /// Computes kp with the Montgomery ladder. Expects k >= 1.
+    fn multiply(&self, p: &Point, k: u64) -> Point {
+        let mut r0 = p.clone();
+        let mut r1 = self.double(p);
+        for bit in (0..k.ilog2()).rev() {
+            if k >> bit & 1 == 1 {
+                r0 = self.add(&r1, &r0, p);
+                r1 = self.double(&r1);
+            } else {
+                r1 = self.add(&r0, &r1, p);
+                r0 = self.double(&r0);
+            }
+        }
+        r0
+    }
+}
+
+/// Runs ECM on the composite `n` until a factor is found or the budget runs out.
+fn ecm(n: &Integer, budget: &Budget) -> Option<Integer> {
+    let last = *ECM_LEVELS.last().unwrap();
+    let levels = ECM_LEVELS.iter().copied().chain(std::iter::repeat(last));
+    let mut sigma = 6;
+    for (b1, curves) in levels {
+        for _ in 0..curves {
+            if budget.is_exhausted() {
+                return None;
+            }
+            if let Some(d) = ecm_curve(n, sigma, b1, budget) {
+                return Some(d);
+            }
+            sigma += 1;
+        }
+    }
+    None
+}
+
+/// Tries a single curve with stage 1 bound `b1`.
+fn ecm_curve(n: &Integer, sigma: u64, b1: u64, budget: &Budget) -> Option<Integer> {
+    let (curve, mut p) = match Curve::suyama(n, sigma) {
+        Ok(curve) => curve,
+        Err(factor) => return factor,
+    };
+
+    // Stage 1: multiply by every prime power below b1.
+    for (i, &prime) in small_primes()
+        .iter()
+        .take_while(|&&prime| u64::from(prime) <= b1)
+        .enumerate()
+    {
+        let prime = u64::from(prime);
+        let mut q = prime;
+        while q * prime <= b1 {
+            q *= prime;
+        }
+        p = curve.multiply(&p, q);
+        if i.is_multiple_of(1024) && budget.is_exhausted() {
+            return None;
+        }
+    }
+    let g = p.z.gcd_ref(n).complete();
+    if g == *n {
+        return None;
+    } else if g != 1 {
+        return Some(g);
+    }
+
+    ecm_stage2(&curve, &p, b1, b1 * ECM_B2_RATIO, budget)
+}
+
+/// Stage 2: look for a single extra prime factor q of the group order in (b1, b2].
+///
+/// Every such q can be written as kD ± j with j < D/2 coprime to D.
+/// kD·P and j·P have the same x-coordinate up to sign exactly when (kD ± j)·P is the
+/// identity modulo some prime factor, so we accumulate the cross products of
+/// their coordinates and take a single gcd at the end.
+fn ecm_stage2(curve: &Curve, p: &Point, b1: u64, b2: u64, budget: &Budget) -> Option<Integer> {
+    let n = curve.n;
+
+    // Odd multiples of p below D/2, stored at index j/2.
+    let double_p = curve.double(p);
+    let mut odd_multiples = vec![p.clone(), curve.add(&double_p, p, p)];
+    for j in (5..ECM_D / 2).step_by(2) {
+        let idx = (j / 2) as usize;
+        let next = curve.add(&odd_multiples[idx - 1], &double_p, &odd_multiples[idx - 2]);
+        odd_multiples.push(next);
+    }
+    let baby_steps: Vec<&Point> = (1..ECM_D / 2)
+        .step_by(2)
+        .filter(|&j| gcd_u64(j, ECM_D) == 1)
+        .map(|j| &odd_multiples[(j / 2) as usize])
+        .collect();
+
+    let giant_step = curve.multiply(p, ECM_D);
+    let mut k = (b1 / ECM_D).max(2);
+    let mut prev = curve.multiply(p, (k - 1) * ECM_D);
+    let mut current = curve.multiply(p, k * ECM_D);
+    let mut acc = Integer::from(1);
+    while (k - 1) * ECM_D <= b2 {
+        for q in &baby_steps {
+            let cross = (&current.x * &q.z).complete() - (&q.x * &current.z).complete();
+            acc *= cross;
+            acc.modulo_mut(n);
+        }
+        let next = curve.add(&current, &giant_step, &prev);
+        prev = std::mem::replace(&mut current, next);
+        k += 1;
+        if k.is_multiple_of(256) && budget.is_exhausted() {
+            break;
+        }
+    }
+
+    let g = acc.gcd(n);
+    (g != 1 && g != *n).then_some(g)
+}
+
+fn gcd_u64(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use proptest::prelude::*;
+
+    fn prime_after(n: u64) -> Integer {
+        Integer::from(n).next_prime()
+    }
+
+    #[test]
+    fn trivial_inputs() {
+        assert_eq!(
+            factorize(&Integer::ZERO, 30, &Budget::unlimited()),
+            Factorization::default()
+        );
+        assert_eq!(
+            factorize(Integer::ONE, 30, &Budget::unlimited()),
+            Factorization::default()
+        );
+    }
+
+    #[test]
+    fn rho_splits_semiprime() {
+        let p = prime_after(1_000_000);
+        let q = prime_after(2_000_000);
+        let n = (&p * &q).complete();
+        let d = pollard_brent(&n, 1, &Budget::unlimited()).unwrap();
+        assert!(d == p || d == q, "{d} is not a factor of {n}");
+    }
+
+    #[test]
+    fn ecm_splits_semiprime() {
+        let p = prime_after(100_000_000_000_000);
+        let q = prime_after(300_000_000_000_000);
+        let n = (&p * &q).complete();
+        let d = ecm(&n, &Budget::unlimited()).unwrap();
+        assert!(d == p || d == q, "{d} is not a factor of {n}");
+    }
+
+    #[test]
+    fn factors_large_numbers() {
+        let p = prime_after(10_000_000_000_000);
+        let q = prime_after(50_000_000_000);
+        let n = (&p * &q).complete() * &q * 12_u8;
+        let factorization = factorize(&n, 30, &Budget::unlimited());
+        assert_eq!(
+            factorization,
+            Factorization {
+                primes: vec![(2.into(), 2), (3.into(), 1), (q, 2), (p, 1)],
+                cofactor: None,
+            }
+        );
+    }
+
+    #[test]
+    fn factors_prime_powers() {
+        let p = prime_after(1_000_000_000_000);
+        let n = p.clone().pow(5);
+        let factorization = factorize(&n, 30, &Budget::unlimited());
+        assert_eq!(factorization.primes, vec![(p, 5)]);
+    }
+
+    #[test]
+    fn exhausted_budget_gives_cofactor() {
+        let p = prime_after(100_000_000_000_000);
+        let q = prime_after(300_000_000_000_000);
+        let n = (&p * &q).complete() * 10_u8;
+        let factorization = factorize(&n, 30, &Budget::new(Duration::ZERO));
+        assert_eq!(factorization.primes, vec![(2.into(), 1), (5.into(), 1)]);
+        assert_eq!(factorization.cofactor, Some(p * q));
+    }
+
+    #[test]
+    fn cofactor_is_coprime_to_primes() {
+        // Splitting a power of a number with several large factors
+        // tends to run out of time halfway through.
+        let a = Integer::parse("21564574195356983538571646100")
+            .unwrap()
+            .complete();
+        let n = a.clone().pow(200);
+        let factorization = factorize(&n, 30, &Budget::new(Duration::from_millis(100)));
+        for (p, count) in &factorization.primes {
+            assert_eq!(count % 200, 0, "{p} has multiplicity {count}");
+            if let Some(cofactor) = &factorization.cofactor {
+                assert!(!cofactor.is_divisible(p));
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn parts_multiply_to_num(n in "[1-9][0-9]{0,40}") {
+            let n = Integer::parse(n).unwrap().complete();
+            let factorization = factorize(&n, 30, &Budget::new(Duration::from_millis(200)));
+            let multiplied = factorization
+                .primes
+                .iter()
+                .map(|(p, count)| p.clone().pow(*count))
+                .product::<Integer>()
+                * factorization.cofactor.unwrap_or(Integer::from(1));
+            prop_assert_eq!(multiplied, n.max(Integer::from(1)));
+        }
+    }
+}
@@ -0,0 +1,81 @@
+//! Number theory routines that are too heavy to live inside a single nerd.
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+pub mod factorize;
+pub mod primecount;
+
+/// A limit on how long an expensive computation may run for.
+///
+/// Computations are expected to check `is_exhausted` regularly and
+/// give up with a partial result once it returns true.
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    deadline: Option<Instant>,
+    /// Shared with every budget derived from this one.
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Budget {
+    pub fn new(duration: Duration) -> Self {
+        Self {
+            deadline: Some(Instant::now() + duration),
+            cancelled: Arc::default(),
+        }
+    }
+
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// A budget which runs out when this one does, or after `duration`, whichever comes first.
+    pub fn limited_to(&self, duration: Duration) -> Self {
+        let deadline = Instant::now() + duration;
+        Self {
+            deadline: Some(self.deadline.map_or(deadline, |d| d.min(deadline))),
+            cancelled: self.cancelled.clone(),
+        }
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.deadline
+    }
+
+    /// Exhausts this budget and every budget derived from it right away.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cancel_propagates() {
+        let budget = Budget::unlimited();
+        let derived = budget.limited_to(Duration::from_secs(60));
+        assert!(!derived.is_exhausted());
+        budget.cancel();
+        assert!(budget.is_exhausted());
+        assert!(derived.is_exhausted());
+    }
+
+    #[test]
+    fn earliest_deadline_wins() {
+        let budget = Budget::new(Duration::ZERO);
+        assert!(budget.limited_to(Duration::from_secs(60)).is_exhausted());
+        assert!(Budget::unlimited()
+            .limited_to(Duration::ZERO)
+            .is_exhausted());
+    }
+}
@@ -0,0 +1,186 @@
+//! Counting the primes up to n, π(n).
+//!
+//! Small n are counted by sieving, larger n with the Meissel-Lucy recurrence,
+//! which only needs O(√n) memory and around n^(3/4) steps.
+//! Beyond that π(n) is only estimated, along with bounds it is known to lie between.
+use rug::Integer;
+
+use super::{factorize::small_primes, Budget};
+
+/// Primes up to this are counted with a segmented sieve.
+const SIEVE_MAX: u64 = 1 << 24;
+/// Numbers covered by each segment of the sieve.
+const SEGMENT_LEN: u64 = 1 << 16;
+/// Primes up to this can be counted within a second or so.
+pub const EXACT_MAX: u64 = 1 << 36;
+
+/// π(n), if n is small enough to count the primes up to it within the budget.
+pub fn prime_pi(n: u64, budget: &Budget) -> Option<u64> {
+    if n <= SIEVE_MAX {
+        sieve_count(n, budget)
+    } else if n <= EXACT_MAX {
+        lucy_count(n, budget)
+    } else {
+        None
+    }
+}
+
+/// Counts the primes up to `n` by sieving one segment at a time.
+fn sieve_count(n: u64, budget: &Budget) -> Option<u64> {
+    let mut count = 0;
+    let mut composite = vec![false; SEGMENT_LEN as usize];
+    let mut low = 2;
+    while low <= n {
+        if budget.is_exhausted() {
+            return None;
+        }
+        let high = n.min(low + SEGMENT_LEN - 1);
+        composite.fill(false);
+        for &p in small_primes() {
+            let p = u64::from(p);
+            if p * p > high {
+                break;
+            }
+            let first = (p * p).max(low.div_ceil(p) * p);
+            for multiple in (first..=high).step_by(p as usize) {
+                composite[(multiple - low) as usize] = true;
+            }
+        }
+        count += composite[..=(high - low) as usize]
+            .iter()
+            .filter(|&&c| !c)
+            .count() as u64;
+        low = high + 1;
+    }
+    Some(count)
+}
+
+/// Counts the primes up to `n` with the Meissel-Lucy recurrence.
+///
+/// S(v) starts as the count of 2..=v and, after sieving out the multiples of each prime p
+/// whose smallest prime factor is p, is updated as S(v) -= S(v/p) - S(p-1).
+/// Only the values v = n/i are ever needed, which are either at most √n or n/i for i at most √n.
+fn lucy_count(n: u64, budget: &Budget) -> Option<u64> {
+    let root = n.isqrt() as usize;
+    // `small[v]` is S(v) and `large[i]` is S(n/i).
+    let mut small: Vec<u64> = (0..=root as u64).map(|v| v.saturating_sub(1)).collect();
+    let mut large: Vec<u64> = (0..=root as u64)
+        .map(|i| n.checked_div(i).map_or(0, |v| v - 1))
+        .collect();
+    for p in 2..=root {
+        if small[p] == small[p - 1] {
+            continue;
+        }
+        if budget.is_exhausted() {
+            return None;
+        }
+        let below = small[p - 1];
+        let square = (p * p) as u64;
+        let last = root.min((n / square) as usize);
+        for i in 1..=last {
+            let d = i * p;
+            let s = if d <= root {
+                large[d]
+            } else {
+                small[(n / d as u64) as usize]
+            };
+            large[i] -= s - below;
+        }
+        for v in (p * p..=root).rev() {
+            small[v] -= small[v / p] - below;
+        }
+    }
+    Some(large[1])
+}
+
+/// The natural logarithm of `n` > 0, which may be too large for an `f64`.
+fn ln(n: &Integer) -> f64 {
+    let (mantissa, exp) = n.to_f64_exp();
+    mantissa.ln() + f64::from(exp) * std::f64::consts::LN_2
+}
+
+/// An estimate of π(n), along with bounds it is known to lie between,
+/// all as natural logarithms since they may be too large for an `f64`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub ln_low: f64,
+    pub ln_estimate: f64,
+    pub ln_high: f64,
+}
+
+/// Estimates π(n) for n ≥ 355991, where Dusart's bounds
+/// n/ln n (1 + 1/ln n) ≤ π(n) ≤ n/ln n (1 + 1/ln n + 2.51/ln² n) hold.
+/// The estimate is the start of the asymptotic series for li(n), n/ln n (1 + 1/ln n + 2/ln² n).
+pub fn estimate_prime_pi(n: &Integer) -> Option<Estimate> {
+    if *n < 355_991 {
+        return None;
+    }
+    let ln_n = ln(n);
+    let ln_ratio = ln_n - ln_n.ln();
+    let series = |c: f64| ln_ratio + (1.0 + 1.0 / ln_n + c / (ln_n * ln_n)).ln();
+    Some(Estimate {
+        ln_low: series(0.0),
+        ln_estimate: series(2.0),
+        ln_high: series(2.51),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+    use rug::ops::Pow;
+
+    /// π(n) from the primes below 2^20.
+    fn reference(n: u64) -> u64 {
+        small_primes().partition_point(|&p| u64::from(p) <= n) as u64
+    }
+
+    #[test]
+    fn known_values() {
+        let budget = Budget::unlimited();
+        for (n, pi) in [
+            (0, 0),
+            (1, 0),
+            (2, 1),
+            (100, 25),
+            (1_000_000, 78_498),
+            (10_000_000, 664_579),
+            (100_000_000, 5_761_455),
+            (10_000_000_000, 455_052_511),
+        ] {
+            assert_eq!(prime_pi(n, &budget), Some(pi), "π({n})");
+        }
+        assert_eq!(prime_pi(EXACT_MAX + 1, &budget), None);
+    }
+
+    #[test]
+    fn bounds_known_values() {
+        for (n, pi) in [
+            (Integer::from(1_000_000), 78_498_f64),
+            (Integer::from(10_000_000_000_u64), 455_052_511.0),
+            (Integer::from(10).pow(24), 18_435_599_767_349_200_867_866.0),
+        ] {
+            let estimate = estimate_prime_pi(&n).unwrap();
+            assert!(estimate.ln_low <= pi.ln() && pi.ln() <= estimate.ln_high);
+            assert!((estimate.ln_estimate - pi.ln()).abs() < 0.01);
+        }
+        assert!(estimate_prime_pi(&Integer::from(1000)).is_none());
+        // Far too large for an f64.
+        let huge = Integer::from(10).pow(5000);
+        assert!(estimate_prime_pi(&huge).unwrap().ln_estimate > 11_000.0);
+    }
+
+    proptest! {
+        #[test]
+        fn sieve_counts(n in 0..100_000u64) {
+            prop_assert_eq!(sieve_count(n, &Budget::unlimited()), Some(reference(n)));
+        }
+
+        #[test]
+        fn lucy_counts(n in 2..1_000_000u64) {
+            prop_assert_eq!(lucy_count(n, &Budget::unlimited()), Some(reference(n)));
+        }
+    }
+}
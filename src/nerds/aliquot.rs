@@ -0,0 +1,286 @@
+//! Aliquot sequences, where each term is the sum of the proper divisors of the one before,
+//! i.e. s(n) = σ(n) - n.
+//!
+//! Most sequences end at 1 after reaching a prime, some reach a perfect number or a cycle
+//! of amicable or sociable numbers, and some, like the one starting at 276, grow for as long
+//! as anyone has followed them.
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use rug::Integer;
+use tokio::sync::mpsc;
+
+use super::{
+    arithmetic::divisor_power_sum, blocking, Category, Class, Fact, Nerd, NerdFuture, NerdResult,
+    NumberContext, Stopped,
+};
+use crate::math::{factorize::factorize, Budget};
+
+/// Sequences are followed for at most this many steps,
+/// which is enough to go around the longest known sociable cycle of 28 numbers.
+const MAX_STEPS: usize = 50;
+/// Terms larger than this may take too long to factor, so the sequence isn't followed past them.
+const MAX_TERM_BITS: u32 = 160;
+/// How long to search for the prime factors of each term.
+const TERM_FACTOR_BUDGET: Duration = Duration::from_millis(100);
+/// Untouchable numbers are only known below this.
+const UNTOUCHABLE_LIMIT: usize = 1000;
+
+/// How far an aliquot sequence was followed.
+#[derive(Debug, PartialEq, Eq)]
+enum Ending {
+    /// The last term is 1, whose proper divisors sum to 0.
+    Terminates,
+    /// The term after the last one is the term at this index, and the sequence repeats from there.
+    Cycle { start: usize },
+    /// The sequence was too long or its terms too large to follow any further.
+    Unknown,
+    /// A term couldn't be factored in time, so the sequence could be followed further with more time.
+    OutOfTime,
+}
+
+/// The sum of the proper divisors of `n`, if it could be factored in time.
+fn aliquot_sum(n: &Integer, rounds: u32, budget: &Budget) -> Option<Integer> {
+    let factorization = factorize(n, rounds, &budget.limited_to(TERM_FACTOR_BUDGET));
+    if factorization.cofactor.is_some() {
+        return None;
+    }
+    Some(divisor_power_sum(&factorization.primes, 1) - n)
+}
+
+/// Follows the aliquot sequence starting at `n` > 0 until it ends or repeats, or as far as the bounds allow.
+fn walk(n: &Integer, rounds: u32, budget: &Budget) -> (Vec<Integer>, Ending) {
+    let mut terms = vec![n.clone()];
+    loop {
+        // Note: This can't fail, there is always at least one term.
+        let last = terms.last().unwrap();
+        if *last == 1 {
+            return (terms, Ending::Terminates);
+        }
+        if terms.len() > MAX_STEPS || last.significant_bits() > MAX_TERM_BITS {
+            return (terms, Ending::Unknown);
+        }
+        if budget.is_exhausted() {
+            return (terms, Ending::OutOfTime);
+        }
+        let Some(next) = aliquot_sum(last, rounds, budget) else {
+            return (terms, Ending::OutOfTime);
+        };
+        if let Some(start) = terms.iter().position(|term| *term == next) {
+            return (terms, Ending::Cycle { start });
+        }
+        terms.push(next);
+    }
+}
+
+/// Whether each number below `UNTOUCHABLE_LIMIT` is untouchable,
+/// i.e. isn't the sum of the proper divisors of any number.
+fn untouchables() -> &'static [bool] {
+    static UNTOUCHABLES: OnceLock<Vec<bool>> = OnceLock::new();
+    UNTOUCHABLES.get_or_init(|| {
+        // A composite m has a proper divisor of at least √m, so s(m) > √m,
+        // and a prime has s(m) = 1. Only m < limit² can have s(m) < limit.
+        let limit = UNTOUCHABLE_LIMIT;
+        let mut sums = vec![0; limit * limit];
+        for d in 1..sums.len() {
+            for multiple in (2 * d..sums.len()).step_by(d) {
+                sums[multiple] += d;
+            }
+        }
+        let mut untouchable = vec![true; limit];
+        for &sum in &sums[1..] {
+            if sum < limit {
+                untouchable[sum] = false;
+            }
+        }
+        untouchable
+    })
+}
+
+fn steps(count: usize) -> String {
+    match count {
+        1 => "(#1) step".to_owned(),
+        _ => format!("(#{count}) steps"),
+    }
+}
+
+fn chain(terms: &[Integer], then: Option<&Integer>) -> String {
+    let links: Vec<_> = terms
+        .iter()
+        .chain(then)
+        .map(|t| format!("(#{t})"))
+        .collect();
+    links.join(" → ")
+}
+
+/// Expects n > 0.
+pub async fn aliquot(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let n = ctx.n();
+    let untouchable = match n.to_usize().filter(|&n| n < UNTOUCHABLE_LIMIT) {
+        // The sieve takes a moment the first time.
+        Some(n) => blocking(move || untouchables()[n]).await,
+        None => false,
+    };
+    if untouchable {
+        tx.send(Fact::basic(
+            Category::Divisibility,
+            1,
+            "Is untouchable: it isn't the sum of the proper divisors of any number.",
+        ))
+        .await?;
+        tx.send(Fact::class(Class::Untouchable)).await?;
+    }
+
+    let (terms, ending) = {
+        let n = ctx.shared_n();
+        let rounds = ctx.prime_rounds();
+        let budget = ctx.budget().clone();
+        blocking(move || walk(&n, rounds, &budget)).await
+    };
+    let count = terms.len() - 1;
+    let text = match ending {
+        // Nothing to say about 1 and the primes, which go straight to 1.
+        Ending::Terminates if count <= 1 => return Ok(()),
+        Ending::Terminates => format!(
+            "Has an aliquot sequence which ends at (#1) after {}: {}.",
+            steps(count),
+            chain(&terms, None)
+        ),
+        // Perfect numbers, which the factors nerd already points out.
+        Ending::Cycle { start: 0 } if count == 0 => return Ok(()),
+        Ending::Cycle { start: 0 } => {
+            let (description, class) = if count == 1 {
+                ("an amicable number", Class::Amicable)
+            } else {
+                ("a sociable number", Class::Sociable)
+            };
+            tx.send(Fact::class(class)).await?;
+            format!(
+                "Is {description}: the sums of proper divisors go around a cycle of (#{}) numbers, {}.",
+                count + 1,
+                chain(&terms, Some(n))
+            )
+        }
+        Ending::Cycle { start } => {
+            let cycle = match &terms[start..] {
+                [perfect] => format!("the perfect number (#{perfect})"),
+                [a, b] => format!("the amicable pair (#{a}) and (#{b})"),
+                sociable => format!("a cycle of (#{}) sociable numbers", sociable.len()),
+            };
+            format!(
+                "Has an aliquot sequence which reaches {cycle} after {}: {}.",
+                steps(start),
+                chain(&terms, Some(&terms[start]))
+            )
+        }
+        Ending::Unknown | Ending::OutOfTime => format!(
+            "Has an aliquot sequence which was only followed for {}: {} → ….",
+            steps(count),
+            chain(&terms, None)
+        ),
+    };
+    tx.send(Fact::basic(Category::Sequences, 1, text)).await?;
+    if ending == Ending::OutOfTime {
+        return Err(Stopped::OutOfTime);
+    }
+    Ok(())
+}
+
+pub struct Aliquot;
+
+impl Nerd for Aliquot {
+    fn name(&self) -> &'static str {
+        "aliquot"
+    }
+
+    fn description(&self) -> &'static str {
+        "Where the aliquot sequence, of sums of proper divisors, leads, and whether the number is amicable, sociable or untouchable."
+    }
+
+    fn applies_to(&self, ctx: &NumberContext) -> bool {
+        *ctx.n() > 0
+    }
+
+    fn max_bits(&self) -> Option<u32> {
+        Some(MAX_TERM_BITS)
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(aliquot(ctx, tx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk_from(n: u32) -> (Vec<u32>, Ending) {
+        let (terms, ending) = walk(&Integer::from(n), 30, &Budget::unlimited());
+        let terms = terms.iter().map(|t| t.to_u32().unwrap()).collect();
+        (terms, ending)
+    }
+
+    #[test]
+    fn walks() {
+        assert_eq!(walk_from(1), (vec![1], Ending::Terminates));
+        assert_eq!(walk_from(7), (vec![7, 1], Ending::Terminates));
+        assert_eq!(
+            walk_from(12),
+            (vec![12, 16, 15, 9, 4, 3, 1], Ending::Terminates)
+        );
+        assert_eq!(walk_from(28), (vec![28], Ending::Cycle { start: 0 }));
+        assert_eq!(walk_from(95), (vec![95, 25, 6], Ending::Cycle { start: 2 }));
+        assert_eq!(walk_from(220), (vec![220, 284], Ending::Cycle { start: 0 }));
+        assert_eq!(
+            walk_from(562),
+            (vec![562, 284, 220], Ending::Cycle { start: 1 })
+        );
+        assert_eq!(
+            walk_from(12496),
+            (
+                vec![12496, 14288, 15472, 14536, 14264],
+                Ending::Cycle { start: 0 }
+            )
+        );
+        assert_eq!(walk_from(14316).0.len(), 28);
+        let (terms, ending) = walk(&Integer::from(276), 30, &Budget::unlimited());
+        assert_eq!(ending, Ending::Unknown);
+        assert!(
+            terms.len() > MAX_STEPS || terms[terms.len() - 1].significant_bits() > MAX_TERM_BITS
+        );
+    }
+
+    #[test]
+    fn finds_untouchables() {
+        let found: Vec<_> = (0..300).filter(|&n| untouchables()[n]).collect();
+        // OEIS A005114. 0 isn't, it is the sum of the proper divisors of 1.
+        assert_eq!(
+            found,
+            [
+                2, 5, 52, 88, 96, 120, 124, 146, 162, 188, 206, 210, 216, 238, 246, 248, 262, 268,
+                276, 288, 290, 292
+            ]
+        );
+    }
+
+    #[test]
+    fn describes_cycles() {
+        crate::test_harness!(|| {
+            let (tx, mut rx) = mpsc::channel(2);
+            aliquot(Arc::new(NumberContext::new(Integer::from(284))), tx)
+                .await
+                .unwrap();
+            assert_eq!(rx.recv().await, Some(Fact::class(Class::Amicable)));
+            assert_eq!(
+                rx.recv().await,
+                Some(Fact::basic(
+                    Category::Sequences,
+                    1,
+                    "Is an amicable number: the sums of proper divisors go around a cycle of (#2) numbers, (#284) → (#220) → (#284)."
+                ))
+            );
+        });
+    }
+}
@@ -0,0 +1,309 @@
+//! Arithmetic functions, which are cheap to compute once the prime factorization is known.
+use std::sync::Arc;
+
+use rug::{ops::Pow, Complete, Integer};
+use tokio::sync::mpsc;
+
+use super::{
+    Category, Class, Fact, Nerd, NerdFuture, NerdResult, NumberContext, Stopped, PRIMALITY_MAX_BITS,
+};
+use crate::math::factorize::Factorization;
+
+/// σ₂ and σ₃ have two and three times as many digits as the number,
+/// which isn't worth showing for large numbers.
+const POWER_SUMS_MAX_BITS: u32 = 256;
+
+/// τ(n), the number of divisors.
+pub(super) fn divisor_count(factors: &[(Integer, u32)]) -> Integer {
+    factors.iter().map(|(_, e)| Integer::from(e + 1)).product()
+}
+
+/// σₖ(n), the sum of the k-th powers of the divisors, for k ≥ 1.
+/// Each prime power contributes 1 + pᵏ + p²ᵏ + ... + pᵉᵏ = (p⁽ᵉ⁺¹⁾ᵏ - 1) / (pᵏ - 1).
+pub(super) fn divisor_power_sum(factors: &[(Integer, u32)], k: u32) -> Integer {
+    factors
+        .iter()
+        .map(|(p, e)| {
+            let pk = p.pow(k).complete();
+            ((&pk).pow(e + 1).complete() - 1_u8) / (pk - 1_u8)
+        })
+        .product()
+}
+
+/// φ(n), how many numbers up to n are coprime to it.
+fn totient(factors: &[(Integer, u32)]) -> Integer {
+    factors
+        .iter()
+        .map(|(p, e)| p.pow(e - 1).complete() * (p - 1_u8).complete())
+        .product()
+}
+
+/// μ(n), which is 0 if n has a square factor, and otherwise -1 to the number of prime factors.
+fn mobius(factors: &[(Integer, u32)]) -> i8 {
+    if factors.iter().any(|&(_, e)| e > 1) {
+        0
+    } else if factors.len().is_multiple_of(2) {
+        1
+    } else {
+        -1
+    }
+}
+
+/// λ(n), the smallest m with aᵐ ≡ 1 (mod n) for every a coprime to n.
+fn carmichael(factors: &[(Integer, u32)]) -> Integer {
+    factors
+        .iter()
+        .map(|(p, e)| match (p.to_u8(), e) {
+            // The powers of two from 8 on are the exception, where λ is half of φ.
+            (Some(2), 3..) => Integer::from(1) << (e - 2),
+            _ => p.pow(e - 1).complete() * (p - 1_u8).complete(),
+        })
+        .fold(Integer::from(1), |acc, lambda| acc.lcm(&lambda))
+}
+
+/// rad(n), the product of the distinct prime factors.
+fn radical(factors: &[(Integer, u32)]) -> Integer {
+    factors.iter().map(|(p, _)| p).product()
+}
+
+fn link_signed(value: i8) -> String {
+    match value {
+        ..=-1 => format!("-(#{})", -value),
+        _ => format!("(#{value})"),
+    }
+}
+
+/// Expects n > 0.
+pub async fn arithmetic(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let Factorization { primes, cofactor } = ctx.factorization().await;
+    // Every function here needs all of the prime factors.
+    if cofactor.is_some() {
+        return Err(Stopped::OutOfTime);
+    }
+
+    let facts = [
+        (
+            9,
+            format!(
+                "τ(n) = (#{}), the number of its divisors.",
+                divisor_count(primes)
+            ),
+        ),
+        (
+            8,
+            format!(
+                "σ(n) = (#{}), the sum of its divisors.",
+                divisor_power_sum(primes, 1)
+            ),
+        ),
+        (
+            7,
+            format!(
+                "φ(n) = (#{}), how many numbers up to it are coprime to it (Euler's totient).",
+                totient(primes)
+            ),
+        ),
+        (
+            6,
+            format!(
+                "λ(n) = (#{}), the smallest m such that aᵐ ≡ 1 (mod n) for every a coprime to it (Carmichael's function).",
+                carmichael(primes)
+            ),
+        ),
+        (
+            5,
+            format!(
+                "μ(n) = {} (the Möbius function).",
+                link_signed(mobius(primes))
+            ),
+        ),
+        (
+            4,
+            format!(
+                "rad(n) = (#{}), the product of its distinct prime factors.",
+                radical(primes)
+            ),
+        ),
+    ];
+    for (priority, text) in facts {
+        tx.send(Fact::basic(Category::Arithmetic, priority, text))
+            .await?;
+    }
+    if ctx.bits() <= POWER_SUMS_MAX_BITS {
+        for (k, subscript, power) in [(2, '₂', "squares"), (3, '₃', "cubes")] {
+            tx.send(Fact::basic(
+                Category::Arithmetic,
+                3,
+                format!(
+                    "σ{subscript}(n) = (#{}), the sum of the {power} of its divisors.",
+                    divisor_power_sum(primes, k)
+                ),
+            ))
+            .await?;
+        }
+    }
+
+    let max_exponent = primes.iter().map(|&(_, e)| e).max().unwrap_or(0);
+    let min_exponent = primes.iter().map(|&(_, e)| e).min().unwrap_or(0);
+    if max_exponent <= 1 {
+        tx.send(Fact::basic(
+            Category::Divisibility,
+            1,
+            "Is squarefree: no square other than (#1) divides it.",
+        ))
+        .await?;
+        tx.send(Fact::class(Class::Squarefree)).await?;
+    }
+    if max_exponent <= 2 {
+        tx.send(Fact::class(Class::CubeFree)).await?;
+        // Squarefree says more, so cube-free is only worth saying by itself.
+        if max_exponent == 2 {
+            tx.send(Fact::basic(
+                Category::Divisibility,
+                1,
+                "Is cube-free: no cube other than (#1) divides it.",
+            ))
+            .await?;
+        }
+    }
+    if min_exponent != 1 {
+        tx.send(Fact::basic(
+            Category::Divisibility,
+            1,
+            "Is powerful: the square of each of its prime factors divides it.",
+        ))
+        .await?;
+        tx.send(Fact::class(Class::Powerful)).await?;
+    }
+    Ok(())
+}
+
+pub struct Arithmetic;
+
+impl Nerd for Arithmetic {
+    fn name(&self) -> &'static str {
+        "arithmetic"
+    }
+
+    fn description(&self) -> &'static str {
+        "Arithmetic functions like the number of divisors and Euler's totient, and whether the number is squarefree or powerful."
+    }
+
+    fn applies_to(&self, ctx: &NumberContext) -> bool {
+        *ctx.n() > 0
+    }
+
+    fn max_bits(&self) -> Option<u32> {
+        Some(PRIMALITY_MAX_BITS)
+    }
+
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(arithmetic(ctx, tx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    use crate::math::{factorize::factorize, Budget};
+
+    fn prime_factors(n: u64) -> Vec<(Integer, u32)> {
+        factorize(&Integer::from(n), 30, &Budget::unlimited()).primes
+    }
+
+    fn gcd(a: u64, b: u64) -> u64 {
+        if b == 0 {
+            a
+        } else {
+            gcd(b, a % b)
+        }
+    }
+
+    fn mod_pow(a: u64, mut e: u64, n: u64) -> u64 {
+        let mut result = 1 % n;
+        let mut base = a % n;
+        while e > 0 {
+            if e % 2 == 1 {
+                result = result * base % n;
+            }
+            base = base * base % n;
+            e /= 2;
+        }
+        result
+    }
+
+    #[test]
+    fn known_values() {
+        let fs = prime_factors(12);
+        assert_eq!(divisor_count(&fs), 6);
+        assert_eq!(divisor_power_sum(&fs, 1), 28);
+        assert_eq!(divisor_power_sum(&fs, 2), 1 + 4 + 9 + 16 + 36 + 144);
+        assert_eq!(totient(&fs), 4);
+        assert_eq!(carmichael(&fs), 2);
+        assert_eq!(mobius(&fs), 0);
+        assert_eq!(radical(&fs), 6);
+
+        // The empty product, for 1.
+        assert_eq!(divisor_count(&[]), 1);
+        assert_eq!(mobius(&[]), 1);
+        assert_eq!(carmichael(&prime_factors(1)), 1);
+
+        assert_eq!(carmichael(&prime_factors(561)), 80);
+        assert_eq!(carmichael(&prime_factors(64)), 16);
+        assert_eq!(mobius(&prime_factors(30)), -1);
+        assert_eq!(link_signed(-1), "-(#1)");
+    }
+
+    #[test]
+    fn classifies() {
+        crate::test_harness!(|| {
+            let classes = |n: u32| async move {
+                let (tx, mut rx) = mpsc::channel(32);
+                arithmetic(Arc::new(NumberContext::new(Integer::from(n))), tx)
+                    .await
+                    .unwrap();
+                let mut classes = Vec::new();
+                while let Some(fact) = rx.recv().await {
+                    if let Fact::Class { class } = fact {
+                        classes.push(class);
+                    }
+                }
+                classes
+            };
+            assert_eq!(classes(30).await, [Class::Squarefree, Class::CubeFree]);
+            assert_eq!(classes(36).await, [Class::CubeFree, Class::Powerful]);
+            assert_eq!(classes(72).await, [Class::Powerful]);
+            assert_eq!(classes(24).await, []);
+        });
+    }
+
+    proptest! {
+        #[test]
+        fn matches_definitions(n in 1..3_000u64) {
+            let fs = prime_factors(n);
+            let divisors: Vec<u64> = (1..=n).filter(|d| n % d == 0).collect();
+            prop_assert_eq!(divisor_count(&fs), divisors.len());
+            for k in 1..=3 {
+                prop_assert_eq!(divisor_power_sum(&fs, k), divisors.iter().map(|d| d.pow(k)).sum::<u64>());
+            }
+            let coprime: Vec<u64> = (1..=n).filter(|&a| gcd(a, n) == 1).collect();
+            prop_assert_eq!(totient(&fs), coprime.len());
+            let lambda = (1..=n.max(1))
+                .find(|&m| coprime.iter().all(|&a| mod_pow(a, m, n) == 1 % n))
+                .unwrap();
+            prop_assert_eq!(carmichael(&fs), lambda);
+            let squarefree = (2..=n).all(|d| n % (d * d) != 0);
+            let mu = if !squarefree { 0 } else if fs.len().is_multiple_of(2) { 1 } else { -1 };
+            prop_assert_eq!(mobius(&fs), mu);
+            let rad: u64 = (2..=n).filter(|&p| n % p == 0 && (2..p).all(|q| p % q != 0)).product();
+            prop_assert_eq!(radical(&fs), rad);
+        }
+    }
+}
@@ -0,0 +1,182 @@
+//! Quantities derived from the number under analysis which more than one nerd needs.
+//!
+//! Everything here is computed lazily and at most once per request.
+//! If a second nerd asks for something while it is still being computed,
+//! it waits for the first computation to finish instead of starting its own.
+use std::{
+    sync::{Arc, OnceLock},
+    time::Duration,
+};
+
+use rug::{integer::IsPrime, Complete, Integer};
+use tokio::sync::OnceCell;
+
+use super::blocking;
+use crate::math::{
+    factorize::{factorize, Factorization},
+    Budget,
+};
+
+/// Number of Miller-Rabin rounds used by the primality test, unless set otherwise.
+const PRIME_ROUNDS: u32 = 30;
+
+/// Testing a prime of this many bits for primality takes around a second.
+/// Nerds which need `primality` or `factorization` should skip larger numbers.
+pub const PRIMALITY_MAX_BITS: u32 = 8192;
+
+#[derive(Debug)]
+pub struct NumberContext {
+    n: Arc<Integer>,
+    budget: Budget,
+    factor_budget: Option<Duration>,
+    prime_rounds: u32,
+    digits: OnceLock<String>,
+    primality: OnceCell<IsPrime>,
+    factorization: OnceCell<Factorization>,
+    sqrt_rem: OnceCell<(Integer, Integer)>,
+}
+
+impl NumberContext {
+    pub fn new(n: impl Into<Arc<Integer>>) -> Self {
+        Self {
+            n: n.into(),
+            budget: Budget::unlimited(),
+            factor_budget: None,
+            prime_rounds: PRIME_ROUNDS,
+            digits: OnceLock::new(),
+            primality: OnceCell::new(),
+            factorization: OnceCell::new(),
+            sqrt_rem: OnceCell::new(),
+        }
+    }
+
+    /// Limits how long the nerds may spend on this number in total.
+    /// Unlimited by default.
+    pub fn with_budget(mut self, budget: Budget) -> Self {
+        self.budget = budget;
+        self
+    }
+
+    /// Limits how long may be spent looking for prime factors, within the overall budget.
+    /// If the number isn't completely factored by then, the factorization is partial.
+    pub fn with_factor_budget(mut self, factor_budget: Duration) -> Self {
+        self.factor_budget = Some(factor_budget);
+        self
+    }
+
+    /// Sets how many Miller-Rabin rounds the primality test does.
+    /// More rounds make composites less likely to be called probably prime.
+    pub fn with_prime_rounds(mut self, prime_rounds: u32) -> Self {
+        self.prime_rounds = prime_rounds;
+        self
+    }
+
+    pub fn n(&self) -> &Integer {
+        &self.n
+    }
+
+    /// The number, for moving into blocking work.
+    pub fn shared_n(&self) -> Arc<Integer> {
+        self.n.clone()
+    }
+
+    /// The budget for the whole request. Nerds doing long computations should
+    /// check it regularly, since it is cancelled once they run out of time.
+    pub fn budget(&self) -> &Budget {
+        &self.budget
+    }
+
+    /// The number of Miller-Rabin rounds to use when testing other numbers for primality.
+    pub fn prime_rounds(&self) -> u32 {
+        self.prime_rounds
+    }
+
+    /// The number of bits needed to represent the number, e.g. 3 for 5 = 0b101.
+    pub fn bits(&self) -> u32 {
+        self.n.significant_bits()
+    }
+
+    /// The decimal representation of the number.
+    pub fn digits(&self) -> &str {
+        self.digits.get_or_init(|| self.n.to_string())
+    }
+
+    pub async fn primality(&self) -> IsPrime {
+        *self
+            .primality
+            .get_or_init(|| {
+                let n = self.shared_n();
+                let rounds = self.prime_rounds;
+                blocking(move || n.is_probably_prime(rounds))
+            })
+            .await
+    }
+
+    /// The prime factorization of the number, which may be partial if the number is hard to factor.
+    /// Empty for 0 and 1.
+    pub async fn factorization(&self) -> &Factorization {
+        self.factorization
+            .get_or_init(|| async {
+                if self.primality().await != IsPrime::No {
+                    return Factorization {
+                        primes: vec![(self.n.as_ref().clone(), 1)],
+                        cofactor: None,
+                    };
+                }
+                let n = self.shared_n();
+                let rounds = self.prime_rounds;
+                let budget = match self.factor_budget {
+                    Some(factor_budget) => self.budget.limited_to(factor_budget),
+                    None => self.budget.clone(),
+                };
+                blocking(move || factorize(&n, rounds, &budget)).await
+            })
+            .await
+    }
+
+    /// The integer square root of the number and the remainder,
+    /// i.e. (s, r) such that n = s^2 + r and r <= 2s.
+    pub async fn sqrt_rem(&self) -> &(Integer, Integer) {
+        self.sqrt_rem
+            .get_or_init(|| {
+                let n = self.shared_n();
+                blocking(move || n.sqrt_rem_ref().complete())
+            })
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_between_nerds() {
+        crate::test_harness!(|| {
+            let ctx = Arc::new(NumberContext::new(Integer::from(2_u64.pow(40) * 3)));
+            let (a, b) = tokio::join!(ctx.factorization(), ctx.factorization());
+            assert!(std::ptr::eq(a, b));
+            assert_eq!(a.primes, vec![(2.into(), 40), (3.into(), 1)]);
+        });
+    }
+
+    #[test]
+    fn primes_skip_factoring() {
+        crate::test_harness!(|| {
+            let ctx = NumberContext::new(Integer::from(8191));
+            assert_eq!(ctx.primality().await, IsPrime::Yes);
+            assert_eq!(ctx.factorization().await.primes, vec![(8191.into(), 1)]);
+        });
+    }
+
+    #[test]
+    fn trivial_numbers() {
+        crate::test_harness!(|| {
+            for n in 0..=1 {
+                let ctx = NumberContext::new(Integer::from(n));
+                assert_eq!(ctx.factorization().await, &Factorization::default());
+                assert_eq!(ctx.digits(), n.to_string());
+            }
+        });
+    }
+}
@@ -1,29 +1,43 @@
 use std::sync::Arc;
 
 use num_traits::Euclid;
-use rug::Integer;
 use tokio::sync::mpsc;
 
-use super::Fact;
+use super::{Fact, Nerd, NerdFuture, NerdResult, NumberContext};
 
-pub async fn encodings(n: Arc<Integer>, tx: mpsc::Sender<Fact>) {
-    tx.send(Fact::Form("Binary".to_owned(), format!("{:b}", n.as_ref())))
-        .await
-        .unwrap();
-    tx.send(Fact::Form(
-        "Hexadecimal".to_owned(),
-        format!("{:X}", n.as_ref()),
-    ))
-    .await
-    .unwrap();
+pub async fn encodings(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let n = ctx.n();
+    tx.send(Fact::form(2, "Binary", format!("{n:b}"))).await?;
+    tx.send(Fact::form(1, "Hexadecimal", format!("{n:X}")))
+        .await?;
 
     if let Some(roman) = n.to_u16().and_then(encode_roman) {
-        tx.send(Fact::Form("Roman numerals".to_owned(), roman))
-            .await
-            .unwrap();
+        tx.send(Fact::form(0, "Roman numerals", roman)).await?;
     }
+    Ok(())
 }
 
+pub struct Encodings;
+
+impl Nerd for Encodings {
+    fn name(&self) -> &'static str {
+        "encodings"
+    }
+
+    fn description(&self) -> &'static str {
+        "Binary, hexadecimal and Roman numeral representations."
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(encodings(ctx, tx))
+    }
+}
+
+const ROMAN_UNITS: [&str; 10] = ["", "I", "II", "III", "IV", "V", "VI", "VII", "VIII", "IX"];
+const ROMAN_TENS: [&str; 10] = ["", "X", "XX", "XXX", "XL", "L", "LX", "LXX", "LXXX", "XC"];
+const ROMAN_HUNDREDS: [&str; 10] = ["", "C", "CC", "CCC", "CD", "D", "DC", "DCC", "DCCC", "CM"];
+const ROMAN_THOUSANDS: [&str; 4] = ["", "M", "MM", "MMM"];
+
 fn encode_roman(n: u16) -> Option<String> {
     if n == 0 || n >= 4000 {
         return None;
@@ -34,29 +48,53 @@ fn encode_roman(n: u16) -> Option<String> {
     let (n, h) = n.div_rem_euclid(&10);
     let (_, th) = n.div_rem_euclid(&10);
 
-    let r_u = ["", "I", "II", "III", "IV", "V", "VI", "VII", "VIII", "IX"][u as usize];
-    let r_t = ["", "X", "XX", "XXX", "XL", "L", "LX", "LXX", "LXXX", "XC"][t as usize];
-    let r_h = ["", "C", "CC", "CCC", "CD", "D", "DC", "DCC", "DCCC", "CM"][h as usize];
-    let r_th = ["", "M", "MM", "MMM"][th as usize];
+    let r_u = ROMAN_UNITS[u as usize];
+    let r_t = ROMAN_TENS[t as usize];
+    let r_h = ROMAN_HUNDREDS[h as usize];
+    let r_th = ROMAN_THOUSANDS[th as usize];
 
     Some([r_th, r_h, r_t, r_u].into_iter().collect())
 }
 
+/// The reverse of `encode_roman`, ignoring case.
+/// Only accepts numerals in the same canonical form, so e.g. IIII and IC are rejected.
+pub fn decode_roman(s: &str) -> Option<u16> {
+    let mut rest = s.to_ascii_uppercase();
+    let mut n = 0;
+    for (table, place) in [
+        (&ROMAN_THOUSANDS[..], 1000),
+        (&ROMAN_HUNDREDS[..], 100),
+        (&ROMAN_TENS[..], 10),
+        (&ROMAN_UNITS[..], 1),
+    ] {
+        // The longest match is the right one, e.g. XC rather than X for 90.
+        let (digit, numeral) = table
+            .iter()
+            .enumerate()
+            .filter(|(_, numeral)| rest.starts_with(*numeral))
+            .max_by_key(|(_, numeral)| numeral.len())?;
+        rest.drain(..numeral.len());
+        n += digit as u16 * place;
+    }
+    (rest.is_empty() && n > 0).then_some(n)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     use proptest::prelude::*;
+    use rug::Integer;
 
     #[test]
     fn roundtrip_bin() {
         crate::test_harness!(|(n in "0|1[0-1]*")| {
             let x = Integer::from_str_radix(&n, 2).unwrap();
             let (tx, mut rx) = mpsc::channel(3);
-            encodings(Arc::new(x), tx).await;
+            encodings(Arc::new(NumberContext::new(x)), tx).await.unwrap();
             prop_assert_eq!(
                 rx.recv().await,
-                Some(Fact::Form("Binary".into(), format!("{n}")))
+                Some(Fact::form(2, "Binary", n))
             )
         });
     }
@@ -66,11 +104,11 @@ mod tests {
         crate::test_harness!(|(n in "0|[1-9A-F][0-9A-F]*")| {
             let x = Integer::from_str_radix(&n, 16).unwrap();
             let (tx, mut rx) = mpsc::channel(3);
-            encodings(Arc::new(x), tx).await;
+            encodings(Arc::new(NumberContext::new(x)), tx).await.unwrap();
             rx.recv().await.unwrap();
             prop_assert_eq!(
                 rx.recv().await,
-                Some(Fact::Form("Hexadecimal".into(), format!("{n}")))
+                Some(Fact::form(1, "Hexadecimal", n))
             )
         });
     }
@@ -83,4 +121,18 @@ mod tests {
         assert_eq!(encode_roman(3999), Some("MMMCMXCIX".into()));
         assert_eq!(encode_roman(4000), None);
     }
+
+    #[test]
+    fn roman_decoding() {
+        assert_eq!(decode_roman("mmxxiv"), Some(2024));
+        assert_eq!(decode_roman("MMMCMXCIX"), Some(3999));
+        assert_eq!(decode_roman(""), None);
+        assert_eq!(decode_roman("IIII"), None);
+        assert_eq!(decode_roman("IC"), None);
+        assert_eq!(decode_roman("MMMM"), None);
+        assert_eq!(decode_roman("XIIV"), None);
+        for n in 1..4000 {
+            assert_eq!(decode_roman(&encode_roman(n).unwrap()), Some(n));
+        }
+    }
 }
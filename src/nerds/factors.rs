@@ -1,51 +1,19 @@
 use std::sync::Arc;
 
-use rug::Integer;
+use rug::{Complete, Integer};
 use tokio::sync::mpsc;
 
-use super::Fact;
-
-const LIMIT: u32 = 100_000_000;
-
-/// Expects 2 <= n <= LIMIT.
-fn factors_impl(mut n: u32) -> Vec<(u32, u32)> {
-    let mut factors = Vec::new();
-    let mut two_count = 0;
-    while n % 2 == 0 {
-        n /= 2;
-        two_count += 1;
-    }
-    if two_count != 0 {
-        factors.push((2, two_count));
-    }
-
-    // Note: `factors` will only contain prime numbers.
-    // This is because no composite factor of the number
-    // can be encountered before the factors of that composite factor.
-    let mut i = 3;
-    while n != 1 && i * i <= n {
-        let mut count = 0;
-        while n % i == 0 {
-            n /= i;
-            count += 1;
-        }
-        if count != 0 {
-            factors.push((i, count));
-        }
-        i += 2;
-    }
-    // The remainder must be a prime number.
-    if n != 1 {
-        factors.push((n, 1));
-    }
-    factors
-}
+use super::{
+    arithmetic::divisor_count, Category, Class, Fact, Nerd, NerdFuture, NerdResult, NumberContext,
+    Stopped, PRIMALITY_MAX_BITS,
+};
+use crate::math::factorize::Factorization;
 
 /// The sum of divisors of a number N is multiplicative,
 /// so to compute it we can simply compute it for all prime factors
 /// ans(p^k) = 1 + p^1 + p^2 + p^3 + ... + p^k
 /// and multiply together the result.
-fn sum_of_divisors(factors: &[(u32, u32)]) -> Integer {
+fn sum_of_divisors(factors: &[(Integer, u32)]) -> Integer {
     factors
         .iter()
         .map(|(p, k)| {
@@ -60,19 +28,79 @@ fn sum_of_divisors(factors: &[(u32, u32)]) -> Integer {
         .product()
 }
 
-pub async fn factors(n: Arc<Integer>, tx: mpsc::Sender<Fact>) {
-    let Some(n) = n.to_u32() else {
-        return;
-    };
-    if n <= 1 || n > LIMIT {
-        return;
+/// Numbers with at most this many divisors have all of them listed,
+/// others only the smallest and largest few.
+const DIVISORS_LISTED: usize = 40;
+const DIVISORS_AT_EACH_END: usize = 10;
+/// Numbers with at most this many divisors also have them listed in pairs which multiply to the number.
+const DIVISOR_PAIRS_MAX: usize = 16;
+
+/// The `k` smallest divisors, in order.
+///
+/// Only the smallest `k` divisors of a product of prime powers can be among the smallest `k`
+/// once another prime power is multiplied in, so the rest are dropped after each prime.
+fn smallest_divisors(factors: &[(Integer, u32)], k: usize) -> Vec<Integer> {
+    let mut divisors = vec![Integer::from(1)];
+    for (p, count) in factors {
+        let mut next = Vec::with_capacity(divisors.len() * (*count as usize + 1));
+        for d in divisors {
+            let mut multiple = d.clone();
+            next.push(d);
+            for _ in 0..*count {
+                multiple *= p;
+                next.push(multiple.clone());
+            }
+        }
+        next.sort_unstable();
+        next.truncate(k);
+        divisors = next;
     }
-    let factors = factors_impl(n);
-    if factors.is_empty() {
-        return;
+    divisors
+}
+
+fn link_all<'a>(numbers: impl IntoIterator<Item = &'a Integer>) -> String {
+    let links: Vec<_> = numbers.into_iter().map(|d| format!("(#{d})")).collect();
+    links.join(", ")
+}
+
+/// Lists the divisors of `n`, and the pairs of proper divisors whose product is `n` if there are few.
+/// Expects n to be composite.
+async fn divisors(n: &Integer, primes: &[(Integer, u32)], tx: &mpsc::Sender<Fact>) -> NerdResult {
+    let count = divisor_count(primes);
+    let Some(count) = count.to_usize().filter(|&count| count <= DIVISORS_LISTED) else {
+        let smallest = smallest_divisors(primes, DIVISORS_AT_EACH_END);
+        let largest: Vec<_> = smallest.iter().rev().map(|d| (n / d).complete()).collect();
+        let omitted = count - 2 * DIVISORS_AT_EACH_END;
+        let formatted = format!(
+            "{}, … (#{omitted}) more …, {}",
+            link_all(&smallest),
+            link_all(&largest)
+        );
+        tx.send(Fact::form(3, "Divisors", formatted)).await?;
+        return Ok(());
+    };
+
+    let all = smallest_divisors(primes, count);
+    tx.send(Fact::form(3, "Divisors", link_all(&all))).await?;
+    if count <= DIVISOR_PAIRS_MAX {
+        // Every divisor up to the square root pairs with one from the other end.
+        let pairs: Vec<_> = all[1..count.div_ceil(2)]
+            .iter()
+            .zip(all[count / 2..count - 1].iter().rev())
+            .map(|(d, e)| format!("(#{d})×(#{e})"))
+            .collect();
+        tx.send(Fact::form(3, "Pairs of divisors", pairs.join(", ")))
+            .await?;
     }
+    Ok(())
+}
+
+/// Expects n > 1.
+pub async fn factors(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let n = ctx.n();
+    let Factorization { primes, cofactor } = ctx.factorization().await;
 
-    let factors_text: Vec<_> = factors
+    let mut factors_text: Vec<_> = primes
         .iter()
         .map(|(k, count)| {
             if *count == 1 {
@@ -82,26 +110,82 @@ pub async fn factors(n: Arc<Integer>, tx: mpsc::Sender<Fact>) {
             }
         })
         .collect();
+
+    if let Some(cofactor) = cofactor {
+        factors_text.push(format!("(#{cofactor})"));
+        let formatted = factors_text.join("×");
+        tx.send(Fact::form(4, "Partial prime factorization", formatted))
+            .await?;
+        tx.send(Fact::basic(
+            Category::Divisibility,
+            2,
+            format!("Has a composite factor (#{cofactor}) which could not be factored in time."),
+        ))
+        .await?;
+        return Err(Stopped::OutOfTime);
+    }
+
     let formatted = factors_text.join("×");
-    tx.send(Fact::Form("Prime factorization".into(), formatted))
-        .await
-        .unwrap();
-
-    let divisor_sum = sum_of_divisors(&factors);
-    let (aliquot_order, aliquot_characteristic) = match divisor_sum.partial_cmp(&(2 * n)).unwrap() {
-        std::cmp::Ordering::Less => ("less than", "a deficient"),
-        std::cmp::Ordering::Equal => ("equal to", "a perfect"),
-        std::cmp::Ordering::Greater => ("greater than", "an abundant"),
+    tx.send(Fact::form(4, "Prime factorization", formatted))
+        .await?;
+    let is_prime = matches!(primes.as_slice(), [(_, 1)]);
+    if !is_prime {
+        divisors(n, primes, &tx).await?;
+    }
+
+    let divisor_sum = sum_of_divisors(primes);
+    let twice_n = (n * 2_u8).complete();
+    let (aliquot_order, aliquot_characteristic, class) = match divisor_sum.cmp(&twice_n) {
+        std::cmp::Ordering::Less => ("less than", "a deficient", Class::Deficient),
+        std::cmp::Ordering::Equal => ("equal to", "a perfect", Class::Perfect),
+        std::cmp::Ordering::Greater => ("greater than", "an abundant", Class::Abundant),
     };
-    tx.send(Fact::Basic(format!(
-        "Is {aliquot_order} half the sum of its divisors ((#{divisor_sum})), making it {aliquot_characteristic} number.",
-    )))
-    .await
-    .unwrap();
-    if divisor_sum == 2 * n - 1 {
-        tx.send(Fact::Basic("Is an almost perfect number.".to_string()))
-            .await
-            .unwrap();
+    tx.send(Fact::basic(
+        Category::Divisibility,
+        2,
+        format!(
+            "Is {aliquot_order} half the sum of its divisors ((#{divisor_sum})), making it {aliquot_characteristic} number.",
+        ),
+    ))
+    .await?;
+    tx.send(Fact::class(class)).await?;
+    if divisor_sum == twice_n - 1_u8 {
+        tx.send(Fact::basic(
+            Category::Divisibility,
+            1,
+            "Is an almost perfect number.",
+        ))
+        .await?;
+        tx.send(Fact::class(Class::AlmostPerfect)).await?;
+    }
+    Ok(())
+}
+
+pub struct Factors;
+
+impl Nerd for Factors {
+    fn name(&self) -> &'static str {
+        "factors"
+    }
+
+    fn description(&self) -> &'static str {
+        "The prime factorization, and whether the number is deficient, perfect or abundant."
+    }
+
+    fn applies_to(&self, ctx: &NumberContext) -> bool {
+        *ctx.n() > 1
+    }
+
+    fn max_bits(&self) -> Option<u32> {
+        Some(PRIMALITY_MAX_BITS)
+    }
+
+    fn version(&self) -> u32 {
+        3
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(factors(ctx, tx))
     }
 }
 
@@ -111,6 +195,22 @@ mod tests {
 
     use proptest::prelude::*;
 
+    use crate::math::{factorize::factorize, Budget};
+
+    fn factors_impl(n: u32) -> Vec<(u32, u32)> {
+        let factorization = factorize(&Integer::from(n), 30, &Budget::unlimited());
+        assert_eq!(factorization.cofactor, None);
+        factorization
+            .primes
+            .into_iter()
+            .map(|(p, count)| (p.to_u32().unwrap(), count))
+            .collect()
+    }
+
+    fn prime_factors(n: u32) -> Vec<(Integer, u32)> {
+        factorize(&Integer::from(n), 30, &Budget::unlimited()).primes
+    }
+
     #[test]
     fn factors_format_properly() {
         crate::test_harness!(|| {
@@ -118,16 +218,58 @@ mod tests {
                 ($a:expr, $b:expr) => {
                     let (tx, mut rx) = mpsc::channel(1);
                     tokio::select! {
-                        _ = factors(Arc::new(Integer::from($a)), tx.clone()) => {},
+                        _ = factors(Arc::new(NumberContext::new(Integer::from($a))), tx.clone()) => {},
                         msg = rx.recv() => assert_eq!(
                             msg,
-                            Some(Fact::Form("Prime factorization".to_owned(), $b.into()))
+                            Some(Fact::form(4, "Prime factorization", $b))
                         )
                     }
                 };
             }
             check!(19, "(#19)");
             check!(198900, "(#2)(^(#2))×(#3)(^(#2))×(#5)(^(#2))×(#13)×(#17)");
+            check!(
+                Integer::parse("1000000000000000000000000000000000000000")
+                    .unwrap()
+                    .complete(),
+                "(#2)(^(#39))×(#5)(^(#39))"
+            );
+        });
+    }
+
+    #[test]
+    fn divisors_format_properly() {
+        crate::test_harness!(|| {
+            let forms = |n: Integer| async move {
+                let (tx, mut rx) = mpsc::channel(2);
+                let primes = factorize(&n, 30, &Budget::unlimited()).primes;
+                divisors(&n, &primes, &tx).await.unwrap();
+                drop(tx);
+                let mut forms = Vec::new();
+                while let Some(fact) = rx.recv().await {
+                    if let Fact::Form { form, .. } = fact {
+                        forms.push(form);
+                    }
+                }
+                forms
+            };
+            assert_eq!(
+                forms(Integer::from(12)).await,
+                [
+                    "(#1), (#2), (#3), (#4), (#6), (#12)",
+                    "(#2)×(#6), (#3)×(#4)"
+                ]
+            );
+            assert_eq!(forms(Integer::from(9)).await[1], "(#3)×(#3)");
+            assert_eq!(forms(Integer::from(2 * 3 * 5 * 7 * 11)).await.len(), 1);
+
+            // 2⁶⁴ has 65 divisors.
+            let n = Integer::from(1) << 64;
+            let listed = forms(n).await;
+            assert_eq!(listed.len(), 1);
+            assert!(listed[0].starts_with("(#1), (#2), (#4), "));
+            assert!(listed[0].contains("(#512), … (#45) more …, (#36028797018963968)"));
+            assert!(listed[0].ends_with(", (#18446744073709551616)"));
         });
     }
 
@@ -135,7 +277,7 @@ mod tests {
     fn sum_of_divisors_cases() {
         macro_rules! check {
             ($a:expr, $b:expr) => {
-                let fs = factors_impl($a);
+                let fs = prime_factors($a);
                 assert_eq!(sum_of_divisors(&fs), $b);
             };
         }
@@ -203,6 +345,12 @@ mod tests {
             prop_assert_eq!(fab, merged);
         }
 
+        #[test]
+        fn smallest_divisors_in_order(n in 2..100_000u32, k in 1..50usize) {
+            let expected: Vec<_> = (1..=n).filter(|d| n % d == 0).take(k).collect();
+            prop_assert_eq!(smallest_divisors(&prime_factors(n), k), expected);
+        }
+
         #[test]
         fn sum_of_divisors_is_multiplicative(mut a in 2..10_000u32, mut b in 2..10_000u32) {
             let mut tmp = Integer::from(a);
@@ -211,9 +359,9 @@ mod tests {
             a /= gcd;
             b /= gcd;
             // Now they're both coprime, so we can properly test multiplicativity.
-            let a_sum = sum_of_divisors(&factors_impl(a));
-            let b_sum = sum_of_divisors(&factors_impl(b));
-            prop_assert_eq!(a_sum*b_sum, sum_of_divisors(&factors_impl(a*b)))
+            let a_sum = sum_of_divisors(&prime_factors(a));
+            let b_sum = sum_of_divisors(&prime_factors(b));
+            prop_assert_eq!(a_sum*b_sum, sum_of_divisors(&prime_factors(a*b)))
         }
     }
 }
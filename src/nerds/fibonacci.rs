@@ -4,16 +4,40 @@ use phf::phf_map;
 use rug::{Assign, Complete, Integer};
 use tokio::sync::mpsc;
 
-use super::Fact;
-
-pub async fn fibonacci(n: Arc<Integer>, tx: mpsc::Sender<Fact>) {
-    if is_fib(&n) {
-        tx.send(Fact::Basic(format!(
-            "Is the (#{})th fibonacci number.",
-            fib_index(&n)
-        )))
-        .await
-        .unwrap();
+use super::{blocking, Category, Class, Fact, Nerd, NerdFuture, NerdResult, NumberContext};
+
+pub async fn fibonacci(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let n = ctx.shared_n();
+    let index = blocking(move || is_fib(&n).then(|| fib_index(&n))).await;
+    if let Some(index) = index {
+        tx.send(Fact::basic(
+            Category::Sequences,
+            1,
+            format!("Is the (#{index})th fibonacci number."),
+        ))
+        .await?;
+        tx.send(Fact::class(Class::Fibonacci)).await?;
+    }
+    Ok(())
+}
+
+pub struct Fibonacci;
+
+impl Nerd for Fibonacci {
+    fn name(&self) -> &'static str {
+        "fibonacci"
+    }
+
+    fn description(&self) -> &'static str {
+        "Whether the number is a Fibonacci number, and which one."
+    }
+
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(fibonacci(ctx, tx))
     }
 }
 
@@ -1,63 +1,610 @@
-use std::sync::Arc;
+use std::{
+    any::Any,
+    cmp::Reverse,
+    collections::HashMap,
+    future::Future,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use rug::Integer;
-use tokio::sync::mpsc;
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, task::JoinSet};
 
+use crate::store::{self, Store};
+
+mod aliquot;
+mod arithmetic;
+mod context;
 mod encodings;
 mod factors;
 mod fibonacci;
+mod nearest_primes;
 mod parity;
 mod power_form;
 mod prime;
+mod prime_kinds;
 mod triangular;
 
+pub use context::NumberContext;
+use context::PRIMALITY_MAX_BITS;
+pub use encodings::decode_roman;
+
 #[derive(Default, Debug, Clone)]
 pub struct NumberInfo {
-    pub facts: Vec<String>,
-    /// Alternate forms of the number, e.g. its binary or hex representation.
+    /// Facts grouped by category, with the categories in order and the facts sorted by priority.
+    pub facts: Vec<(Category, Vec<String>)>,
+    /// Alternate forms of the number, e.g. its binary or hex representation, sorted by priority.
     /// Stored as tuple (description, alternate form).
     pub forms: Vec<(String, String)>,
+    /// Names of the nerds which ran out of time before they were done.
+    pub timed_out: Vec<&'static str>,
+    /// Names of the nerds which panicked before they were done.
+    pub failed: Vec<&'static str>,
+    /// Names of the nerds which weren't asked because the number is too large for them.
+    pub skipped: Vec<&'static str>,
+    /// The classes the number was found to belong to, in order.
+    pub classes: Vec<Class>,
+}
+
+impl NumberInfo {
+    /// What the nerds said so far, while they are still busy.
+    pub fn partial(facts: Vec<Fact>) -> Self {
+        let mut info = Self::default();
+        info.set_facts(facts);
+        info
+    }
+
+    /// Whether every nerd which was asked got to finish.
+    /// Incomplete results may be different next time, when the server is less busy.
+    pub fn is_complete(&self) -> bool {
+        self.timed_out.is_empty() && self.failed.is_empty()
+    }
+
+    /// Sorts the facts into a stable order, which doesn't depend on which nerd finished first.
+    /// Ties in priority are broken alphabetically.
+    fn set_facts(&mut self, facts: Vec<Fact>) {
+        let mut basic = Vec::new();
+        let mut forms = Vec::new();
+        self.classes.clear();
+        for fact in facts {
+            match fact {
+                Fact::Basic {
+                    category,
+                    priority,
+                    text,
+                } => basic.push((category, Reverse(priority), text)),
+                Fact::Form {
+                    priority,
+                    description,
+                    form,
+                } => forms.push((Reverse(priority), description, form)),
+                Fact::Class { class } => self.classes.push(class),
+            }
+        }
+        self.classes.sort_unstable();
+        self.classes.dedup();
+
+        basic.sort_unstable();
+        self.facts.clear();
+        for (category, _, text) in basic {
+            match self.facts.last_mut() {
+                Some((last, texts)) if *last == category => texts.push(text),
+                _ => self.facts.push((category, vec![text])),
+            }
+        }
+
+        forms.sort_unstable();
+        self.forms = forms
+            .into_iter()
+            .map(|(_, desc, form)| (desc, form))
+            .collect();
+    }
+}
+
+/// What a fact is about. Facts are shown under a heading for their category,
+/// and the categories are shown in this order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    Primality,
+    Divisibility,
+    Arithmetic,
+    Sequences,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum Fact {
-    Basic(String),
-    Form(String, String),
+impl Category {
+    pub fn heading(&self) -> &'static str {
+        match self {
+            Category::Primality => "Primality",
+            Category::Divisibility => "Divisibility",
+            Category::Arithmetic => "Arithmetic functions",
+            Category::Sequences => "Sequences",
+        }
+    }
 }
 
-pub async fn ask_nerds(n: Arc<Integer>) -> NumberInfo {
+/// Something a nerd has to say about a number.
+/// Within a category, facts with a higher priority are shown first.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Fact {
+    Basic {
+        category: Category,
+        priority: u8,
+        text: String,
+    },
+    /// An alternate form of the number, shown in a table with the others.
+    Form {
+        priority: u8,
+        description: String,
+        form: String,
+    },
+    /// Membership in a class of numbers, which isn't shown by itself,
+    /// but decides which of the manual's class pages are shown.
+    Class { class: Class },
+}
+
+impl Fact {
+    pub fn basic(category: Category, priority: u8, text: impl Into<String>) -> Self {
+        Fact::Basic {
+            category,
+            priority,
+            text: text.into(),
+        }
+    }
+
+    pub fn class(class: Class) -> Self {
+        Fact::Class { class }
+    }
+
+    pub fn form(priority: u8, description: impl Into<String>, form: impl Into<String>) -> Self {
+        Fact::Form {
+            priority,
+            description: description.into(),
+            form: form.into(),
+        }
+    }
+}
+
+/// A class of numbers which nerds can tell membership of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Class {
+    Even,
+    Odd,
+    Prime,
+    MersennePrime,
+    TwinPrime,
+    CousinPrime,
+    SexyPrime,
+    SophieGermainPrime,
+    SafePrime,
+    ChenPrime,
+    Emirp,
+    PalindromicPrime,
+    FermatPrime,
+    WagstaffPrime,
+    ProthPrime,
+    Deficient,
+    Perfect,
+    Abundant,
+    AlmostPerfect,
+    Amicable,
+    Sociable,
+    Untouchable,
+    Squarefree,
+    CubeFree,
+    Powerful,
+    PerfectPower,
+    Square,
+    Fibonacci,
+    Triangular,
+}
+
+impl Class {
+    pub const ALL: &'static [Class] = &[
+        Class::Even,
+        Class::Odd,
+        Class::Prime,
+        Class::MersennePrime,
+        Class::TwinPrime,
+        Class::CousinPrime,
+        Class::SexyPrime,
+        Class::SophieGermainPrime,
+        Class::SafePrime,
+        Class::ChenPrime,
+        Class::Emirp,
+        Class::PalindromicPrime,
+        Class::FermatPrime,
+        Class::WagstaffPrime,
+        Class::ProthPrime,
+        Class::Deficient,
+        Class::Perfect,
+        Class::Abundant,
+        Class::AlmostPerfect,
+        Class::Amicable,
+        Class::Sociable,
+        Class::Untouchable,
+        Class::Squarefree,
+        Class::CubeFree,
+        Class::Powerful,
+        Class::PerfectPower,
+        Class::Square,
+        Class::Fibonacci,
+        Class::Triangular,
+    ];
+
+    /// The name used in rules and the API, e.g. `mersenne-prime`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Class::Even => "even",
+            Class::Odd => "odd",
+            Class::Prime => "prime",
+            Class::MersennePrime => "mersenne-prime",
+            Class::TwinPrime => "twin-prime",
+            Class::CousinPrime => "cousin-prime",
+            Class::SexyPrime => "sexy-prime",
+            Class::SophieGermainPrime => "sophie-germain-prime",
+            Class::SafePrime => "safe-prime",
+            Class::ChenPrime => "chen-prime",
+            Class::Emirp => "emirp",
+            Class::PalindromicPrime => "palindromic-prime",
+            Class::FermatPrime => "fermat-prime",
+            Class::WagstaffPrime => "wagstaff-prime",
+            Class::ProthPrime => "proth-prime",
+            Class::Deficient => "deficient",
+            Class::Perfect => "perfect",
+            Class::Abundant => "abundant",
+            Class::AlmostPerfect => "almost-perfect",
+            Class::Amicable => "amicable",
+            Class::Sociable => "sociable",
+            Class::Untouchable => "untouchable",
+            Class::Squarefree => "squarefree",
+            Class::CubeFree => "cube-free",
+            Class::Powerful => "powerful",
+            Class::PerfectPower => "perfect-power",
+            Class::Square => "square",
+            Class::Fibonacci => "fibonacci",
+            Class::Triangular => "triangular",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Class> {
+        Class::ALL
+            .iter()
+            .copied()
+            .find(|class| class.name() == name)
+    }
+}
+
+/// Why a nerd stopped before it was done.
+#[derive(Debug)]
+pub enum Stopped {
+    /// Nobody is listening to it anymore.
+    Unheard,
+    /// It ran out of time for part of what it had to say, after saying the rest.
+    /// With more time it might say something different, so what it said isn't stored.
+    OutOfTime,
+}
+
+impl From<mpsc::error::SendError<Fact>> for Stopped {
+    fn from(_: mpsc::error::SendError<Fact>) -> Self {
+        Stopped::Unheard
+    }
+}
+
+/// Nerds stop early with an error once nobody is listening to them anymore,
+/// and end with one if they ran out of time.
+pub type NerdResult = Result<(), Stopped>;
+
+pub type NerdFuture = Pin<Box<dyn Future<Output = NerdResult> + Send>>;
+
+/// Somebody who knows a thing or two about numbers.
+pub trait Nerd: Send + Sync {
+    /// A short identifier, used to enable or disable the nerd.
+    fn name(&self) -> &'static str;
+
+    /// What the nerd looks for, shown on the `/about/nerds` page.
+    fn description(&self) -> &'static str;
+
+    /// Whether the nerd could possibly have anything to say about the number.
+    /// Nerds are not asked about numbers they don't apply to.
+    fn applies_to(&self, _ctx: &NumberContext) -> bool {
+        true
+    }
+
+    /// The size in bits above which the nerd would take too long, if there is one.
+    /// Nerds are not asked about numbers larger than this.
+    fn max_bits(&self) -> Option<u32> {
+        None
+    }
+
+    /// Changed whenever the nerd starts finding different facts,
+    /// so that the facts stored by older versions are found again.
+    fn version(&self) -> u32 {
+        1
+    }
+
+    /// Sends everything the nerd knows about the number to `tx`.
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture;
+}
+
+/// Every nerd we know of, in the order they are asked.
+static NERDS: &[&dyn Nerd] = &[
+    &aliquot::Aliquot,
+    &arithmetic::Arithmetic,
+    &encodings::Encodings,
+    &factors::Factors,
+    &fibonacci::Fibonacci,
+    &nearest_primes::NearestPrimes,
+    &parity::Parity,
+    &power_form::PowerForm,
+    &prime::Prime,
+    &prime_kinds::PrimeKinds,
+    &triangular::Triangular,
+];
+
+/// The nerds which are available, and whether they are enabled.
+#[derive(Clone)]
+pub struct Registry {
+    nerds: Vec<(&'static dyn Nerd, bool)>,
+}
+
+impl Default for Registry {
+    /// A registry with every nerd enabled.
+    fn default() -> Self {
+        Self {
+            nerds: NERDS.iter().map(|&nerd| (nerd, true)).collect(),
+        }
+    }
+}
+
+impl Registry {
+    /// Enables or disables the nerd called `name`.
+    /// Returns false if there is no such nerd.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        match self.nerds.iter_mut().find(|(nerd, _)| nerd.name() == name) {
+            Some((_, state)) => {
+                *state = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every nerd, along with whether it is enabled.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static dyn Nerd, bool)> + '_ {
+        self.nerds.iter().copied()
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &'static dyn Nerd> + '_ {
+        self.iter()
+            .filter_map(|(nerd, enabled)| enabled.then_some(nerd))
+    }
+}
+
+/// Runs CPU heavy work on the blocking thread pool, so that it doesn't hold up other requests.
+/// Work that might take long should check the context's budget, as it can't be aborted otherwise.
+async fn blocking<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    match tokio::task::spawn_blocking(f).await {
+        Ok(res) => res,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// How a nerd's run ended.
+struct Report {
+    name: &'static str,
+    version: u32,
+    outcome: std::thread::Result<NerdResult>,
+    /// Everything the nerd said, in order.
+    said: Vec<Fact>,
+    elapsed: Duration,
+}
+
+/// Runs the nerd, passing on what it says to `tx` as soon as it says it,
+/// so that it is heard even if the nerd runs out of time later.
+async fn run_nerd(
+    nerd: &'static dyn Nerd,
+    ctx: Arc<NumberContext>,
+    tx: mpsc::Sender<Fact>,
+) -> Report {
+    let started = Instant::now();
+    let (nerd_tx, mut nerd_rx) = mpsc::channel(1);
+    // Catch panics here rather than in the JoinSet, so we know whose they were.
+    let mut run = AssertUnwindSafe(nerd.run(ctx, nerd_tx)).catch_unwind();
+    let mut said = Vec::new();
+    let outcome = loop {
+        tokio::select! {
+            outcome = &mut run => break outcome,
+            Some(fact) = nerd_rx.recv() => {
+                said.push(fact.clone());
+                // Nobody listening means the nerd is about to be aborted anyway.
+                let _ = tx.send(fact).await;
+            }
+        }
+    };
+    // Dropping the nerd's future closes the channel, so this ends.
+    drop(run);
+    while let Some(fact) = nerd_rx.recv().await {
+        said.push(fact.clone());
+        let _ = tx.send(fact).await;
+    }
+    Report {
+        name: nerd.name(),
+        version: nerd.version(),
+        outcome,
+        said,
+        elapsed: started.elapsed(),
+    }
+}
+
+/// Asks every enabled nerd about the number.
+/// Nerds which are still busy once the context's budget runs out are cancelled.
+///
+/// Nerds whose facts about the number are in the `store` aren't asked again,
+/// and the facts of those which took long enough to find them are added to it.
+pub async fn ask_nerds(
+    registry: &Registry,
+    ctx: Arc<NumberContext>,
+    store: Option<&Store>,
+) -> NumberInfo {
+    ask_nerds_and_report(registry, ctx, store, |_| {}).await
+}
+
+/// Like `ask_nerds`, but also passes every fact to `on_fact` as soon as it is heard.
+pub async fn ask_nerds_and_report(
+    registry: &Registry,
+    ctx: Arc<NumberContext>,
+    store: Option<&Store>,
+    mut on_fact: impl FnMut(&Fact),
+) -> NumberInfo {
+    let mut asked = Vec::new();
+    let mut skipped = Vec::new();
+    for nerd in registry.enabled() {
+        if nerd.max_bits().is_some_and(|max| ctx.bits() > max) {
+            skipped.push(nerd.name());
+        } else if nerd.applies_to(&ctx) {
+            asked.push(nerd);
+        }
+    }
+
+    let mut stored = HashMap::new();
+    if let Some(store) = store {
+        let digits = ctx.digits().to_owned();
+        let versions: Vec<_> = asked.iter().map(|n| (n.name(), n.version())).collect();
+        match store::blocking(store, move |store| store.load(&digits, &versions)).await {
+            Ok(found) => stored = found,
+            Err(err) => eprintln!(
+                "Error: cannot load stored facts about {}: {err}",
+                ctx.digits()
+            ),
+        }
+    }
+
     let (tx, mut rx) = mpsc::channel::<Fact>(1);
+    let mut facts = Vec::new();
+    let mut busy = Vec::new();
+    let mut tasks = JoinSet::new();
+    for nerd in asked {
+        match stored.remove(nerd.name()) {
+            Some(said) => {
+                said.iter().for_each(&mut on_fact);
+                facts.extend(said);
+            }
+            None => {
+                tasks.spawn(run_nerd(nerd, ctx.clone(), tx.clone()));
+                busy.push(nerd.name());
+            }
+        }
+    }
+    drop(tx);
 
-    tokio::spawn(encodings::encodings(n.clone(), tx.clone()));
-    tokio::spawn(factors::factors(n.clone(), tx.clone()));
-    tokio::spawn(fibonacci::fibonacci(n.clone(), tx.clone()));
-    tokio::spawn(parity::parity(n.clone(), tx.clone()));
-    tokio::spawn(power_form::power_form(n.clone(), tx.clone()));
-    tokio::spawn(prime::prime(n.clone(), tx.clone()));
-    tokio::spawn(triangular::triangular(n.clone(), tx.clone()));
-    drop((n, tx));
+    let timeout = async {
+        match ctx.budget().deadline() {
+            Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::pin!(timeout);
+
+    let mut info = NumberInfo {
+        skipped,
+        ..Default::default()
+    };
+    let mut findings = Vec::new();
+    loop {
+        tokio::select! {
+            Some(fact) = rx.recv() => {
+                on_fact(&fact);
+                facts.push(fact);
+            }
+            done = tasks.join_next() => match done {
+                Some(Ok(report)) => {
+                    let name = report.name;
+                    busy.retain(|&n| n != name);
+                    match report.outcome {
+                        Ok(Ok(())) => {
+                            if store.is_some_and(|store| report.elapsed >= store.min_duration()) {
+                                findings.push((name, report.version, report.said));
+                            }
+                        }
+                        // Only happens once nobody is listening anymore.
+                        Ok(Err(Stopped::Unheard)) => {}
+                        // What it said is shown, but may be different next time.
+                        Ok(Err(Stopped::OutOfTime)) => info.timed_out.push(name),
+                        Err(panic) => {
+                            eprintln!(
+                                "Error: nerd {name:?} panicked on {}: {}",
+                                ctx.digits(),
+                                panic_message(&*panic)
+                            );
+                            info.failed.push(name);
+                        }
+                    }
+                }
+                // Tasks catch their own panics and are only aborted after the loop.
+                Some(Err(_)) => {}
+                None => {
+                    // Every nerd is done, but some of what they said may not have been heard yet.
+                    while let Some(fact) = rx.recv().await {
+                        on_fact(&fact);
+                        facts.push(fact);
+                    }
+                    break;
+                }
+            },
+            () = &mut timeout => {
+                // Stop any blocking work as well as the tasks themselves.
+                ctx.budget().cancel();
+                tasks.abort_all();
+                info.timed_out.extend(busy);
+                break;
+            }
+        }
+    }
 
-    let mut info = NumberInfo::default();
-    while let Some(fact) = rx.recv().await {
-        match fact {
-            Fact::Basic(s) => info.facts.push(s),
-            Fact::Form(desc, form) => info.forms.push((desc, form)),
+    if let Some(store) = store.filter(|_| !findings.is_empty()) {
+        let digits = ctx.digits().to_owned();
+        if let Err(err) = store::blocking(store, move |store| store.save(&digits, &findings)).await
+        {
+            eprintln!("Error: cannot store facts about {}: {err}", ctx.digits());
         }
     }
+
+    info.set_facts(facts);
+    info.timed_out.sort_unstable();
+    info.failed.sort_unstable();
+    info.skipped.sort_unstable();
     info
 }
 
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s
+    } else {
+        "unknown panic"
+    }
+}
+
 #[macro_export]
 macro_rules! test_harness {
     (|| $body:block) => {
         // don't use proptest
         tokio::runtime::Builder::new_current_thread()
+            .enable_time()
             .build()
             .unwrap()
             .block_on(async move $body);
     };
     (|($($v:pat in $e:expr),+)| $body:block) => {
         let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
             .build()
             .unwrap();
         proptest!(|($($v in $e),*)| {
@@ -68,3 +615,247 @@ macro_rules! test_harness {
         });
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::time::Duration;
+
+    use proptest::prelude::*;
+    use rug::Integer;
+
+    use crate::math::Budget;
+
+    /// Never has anything to say.
+    struct Slowpoke;
+
+    impl Nerd for Slowpoke {
+        fn name(&self) -> &'static str {
+            "slowpoke"
+        }
+
+        fn description(&self) -> &'static str {
+            "Takes forever to think."
+        }
+
+        fn run(&self, _ctx: Arc<NumberContext>, _tx: mpsc::Sender<Fact>) -> NerdFuture {
+            Box::pin(std::future::pending())
+        }
+    }
+
+    /// Can't handle the truth.
+    struct Panicky;
+
+    impl Nerd for Panicky {
+        fn name(&self) -> &'static str {
+            "panicky"
+        }
+
+        fn description(&self) -> &'static str {
+            "Panics at the sight of a number."
+        }
+
+        fn run(&self, _ctx: Arc<NumberContext>, _tx: mpsc::Sender<Fact>) -> NerdFuture {
+            Box::pin(async { panic!("aaaaa") })
+        }
+    }
+
+    /// Only cares about small numbers.
+    struct Picky;
+
+    impl Nerd for Picky {
+        fn name(&self) -> &'static str {
+            "picky"
+        }
+
+        fn description(&self) -> &'static str {
+            "Won't look at numbers of more than a byte."
+        }
+
+        fn max_bits(&self) -> Option<u32> {
+            Some(8)
+        }
+
+        fn run(&self, _ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+            Box::pin(async move {
+                tx.send(Fact::basic(Category::Sequences, 0, "Fits in a byte."))
+                    .await?;
+                Ok(())
+            })
+        }
+    }
+
+    /// Never takes the time to finish a thought.
+    struct Hasty;
+
+    impl Nerd for Hasty {
+        fn name(&self) -> &'static str {
+            "hasty"
+        }
+
+        fn description(&self) -> &'static str {
+            "Says the first thing that comes to mind."
+        }
+
+        fn run(&self, _ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+            Box::pin(async move {
+                tx.send(Fact::basic(Category::Sequences, 0, "Is probably big."))
+                    .await?;
+                Err(Stopped::OutOfTime)
+            })
+        }
+    }
+
+    fn says(info: &NumberInfo, fact: &str) -> bool {
+        info.facts
+            .iter()
+            .any(|(_, facts)| facts.iter().any(|f| f == fact))
+    }
+
+    #[test]
+    fn unique_names() {
+        let mut names: Vec<_> = NERDS.iter().map(|nerd| nerd.name()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), NERDS.len());
+    }
+
+    #[test]
+    fn class_names() {
+        for &class in Class::ALL {
+            assert_eq!(Class::from_name(class.name()), Some(class));
+            assert_eq!(
+                serde_json::to_value(class).unwrap(),
+                serde_json::json!(class.name())
+            );
+        }
+    }
+
+    #[test]
+    fn disabled_nerds_are_not_asked() {
+        crate::test_harness!(|| {
+            let mut registry = Registry::default();
+            let ctx = Arc::new(NumberContext::new(Integer::from(12)));
+            let info = ask_nerds(&registry, ctx.clone(), None).await;
+            assert!(says(&info, "Is an even number."));
+
+            assert!(registry.set_enabled("parity", false));
+            assert!(!registry.set_enabled("numerology", false));
+            let info = ask_nerds(&registry, ctx, None).await;
+            assert!(!says(&info, "Is an even number."));
+        });
+    }
+
+    #[test]
+    fn slow_nerds_time_out() {
+        crate::test_harness!(|| {
+            let mut registry = Registry::default();
+            registry.nerds.push((&Slowpoke, true));
+            let ctx = NumberContext::new(Integer::from(12))
+                .with_budget(Budget::new(Duration::from_millis(100)));
+            let ctx = Arc::new(ctx);
+            let info = ask_nerds(&registry, ctx.clone(), None).await;
+            assert_eq!(info.timed_out, vec!["slowpoke"]);
+            assert!(says(&info, "Is an even number."));
+            assert!(ctx.budget().is_exhausted());
+        });
+    }
+
+    #[test]
+    fn panicking_nerds_fail_alone() {
+        crate::test_harness!(|| {
+            let mut registry = Registry::default();
+            registry.nerds.push((&Panicky, true));
+            let ctx = Arc::new(NumberContext::new(Integer::from(12)));
+            let info = ask_nerds(&registry, ctx, None).await;
+            assert_eq!(info.failed, vec!["panicky"]);
+            assert!(info.timed_out.is_empty());
+            assert!(says(&info, "Is an even number."));
+        });
+    }
+
+    #[test]
+    fn facts_found_out_of_time_are_not_stored() {
+        crate::test_harness!(|| {
+            let mut registry = Registry::default();
+            registry.nerds.push((&Hasty, true));
+            let store = Store::open(":memory:").unwrap();
+            let ctx = Arc::new(NumberContext::new(Integer::from(12)));
+            let info = ask_nerds(&registry, ctx, Some(&store)).await;
+            assert!(says(&info, "Is probably big."));
+            assert_eq!(info.timed_out, vec!["hasty"]);
+            assert!(!info.is_complete());
+
+            let found = store.load("12", &[("hasty", 1), ("parity", 2)]).unwrap();
+            assert!(!found.contains_key("hasty"));
+            assert!(found.contains_key("parity"));
+        });
+    }
+
+    #[test]
+    fn large_numbers_skip_expensive_nerds() {
+        crate::test_harness!(|| {
+            let mut registry = Registry::default();
+            registry.nerds.push((&Picky, true));
+            let info = ask_nerds(
+                &registry,
+                Arc::new(NumberContext::new(Integer::from(255))),
+                None,
+            )
+            .await;
+            assert!(says(&info, "Fits in a byte."));
+            assert!(info.skipped.is_empty());
+
+            let info = ask_nerds(
+                &registry,
+                Arc::new(NumberContext::new(Integer::from(256))),
+                None,
+            )
+            .await;
+            assert!(!says(&info, "Fits in a byte."));
+            assert_eq!(info.skipped, vec!["picky"]);
+            assert!(says(&info, "Is an even number."));
+        });
+    }
+
+    #[test]
+    fn facts_are_sorted() {
+        let facts = vec![
+            Fact::basic(Category::Sequences, 0, "c"),
+            Fact::form(0, "Hexadecimal", "C"),
+            Fact::basic(Category::Primality, 0, "b"),
+            Fact::basic(Category::Sequences, 1, "d"),
+            Fact::form(1, "Binary", "1100"),
+            Fact::basic(Category::Primality, 0, "a"),
+            Fact::class(Class::Triangular),
+            Fact::class(Class::Even),
+            Fact::class(Class::Triangular),
+        ];
+        let mut expected = NumberInfo::default();
+        expected.set_facts(facts.clone());
+        assert_eq!(
+            expected.facts,
+            vec![
+                (Category::Primality, vec!["a".to_owned(), "b".to_owned()]),
+                (Category::Sequences, vec!["d".to_owned(), "c".to_owned()]),
+            ]
+        );
+        assert_eq!(
+            expected.forms,
+            vec![
+                ("Binary".to_owned(), "1100".to_owned()),
+                ("Hexadecimal".to_owned(), "C".to_owned()),
+            ]
+        );
+        assert_eq!(expected.classes, vec![Class::Even, Class::Triangular]);
+
+        proptest!(|(facts in Just(facts).prop_shuffle())| {
+            let mut info = NumberInfo::default();
+            info.set_facts(facts);
+            prop_assert_eq!(&info.facts, &expected.facts);
+            prop_assert_eq!(&info.forms, &expected.forms);
+            prop_assert_eq!(&info.classes, &expected.classes);
+        });
+    }
+}
@@ -0,0 +1,238 @@
+use std::sync::Arc;
+
+use rug::{integer::IsPrime, Complete, Integer};
+use tokio::sync::mpsc;
+
+use super::{blocking, Category, Fact, Nerd, NerdFuture, NerdResult, NumberContext, Stopped};
+use crate::math::{
+    primecount::{estimate_prime_pi, prime_pi, Estimate, EXACT_MAX},
+    Budget,
+};
+
+/// Above this, the gaps between primes are long enough that finding the nearest ones takes too long.
+const NEAREST_MAX_BITS: u32 = 1024;
+/// The bounds on π(n) are shown with at least this many significant digits,
+/// and more if that is what it takes to tell them apart.
+const MIN_DIGITS: i32 = 3;
+const MAX_DIGITS: i32 = 12;
+
+/// The largest prime below `n`, unless there is none or the budget runs out while looking.
+fn previous_prime(n: &Integer, rounds: u32, budget: &Budget) -> Option<Integer> {
+    if *n <= 2 {
+        return None;
+    }
+    let mut m = (n - 1_u8).complete();
+    if m.is_even() && m > 2 {
+        m -= 1;
+    }
+    // Every odd m counted down from here reaches 3, which is prime.
+    while m.is_probably_prime(rounds) == IsPrime::No {
+        if budget.is_exhausted() {
+            return None;
+        }
+        m -= 2;
+    }
+    Some(m)
+}
+
+fn ordinal_suffix(k: u64) -> &'static str {
+    match (k % 100, k % 10) {
+        (11..=13, _) => "th",
+        (_, 1) => "st",
+        (_, 2) => "nd",
+        (_, 3) => "rd",
+        _ => "th",
+    }
+}
+
+/// e to the power of `ln_value` in scientific notation with `digits` significant digits,
+/// rounded with `round`.
+fn scientific(ln_value: f64, digits: i32, round: fn(f64) -> f64) -> String {
+    let log10 = ln_value / std::f64::consts::LN_10;
+    let mut exp = log10.floor();
+    let scale = 10_f64.powi(digits - 1);
+    let mut mantissa = round(10_f64.powf(log10 - exp) * scale) / scale;
+    if mantissa >= 10.0 {
+        mantissa /= 10.0;
+        exp += 1.0;
+    }
+    let precision = (digits - 1) as usize;
+    format!("{mantissa:.precision$}×10(^{exp})")
+}
+
+fn describe_estimate(estimate: Estimate) -> String {
+    let distinct = |digits| {
+        scientific(estimate.ln_low, digits, f64::round)
+            != scientific(estimate.ln_high, digits, f64::round)
+    };
+    let digits = (MIN_DIGITS..MAX_DIGITS)
+        .find(|&digits| distinct(digits))
+        .unwrap_or(MAX_DIGITS);
+    // Rounded outwards, so that they are still bounds.
+    format!(
+        "There are about {} primes up to it, between {} and {}.",
+        scientific(estimate.ln_estimate, digits, f64::round),
+        scientific(estimate.ln_low, digits, f64::floor),
+        scientific(estimate.ln_high, digits, f64::ceil)
+    )
+}
+
+pub async fn nearest_primes(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let n = ctx.n();
+    let mut complete = true;
+    if ctx.bits() <= NEAREST_MAX_BITS {
+        let (previous, next) = {
+            let n = ctx.shared_n();
+            let rounds = ctx.prime_rounds();
+            let budget = ctx.budget().clone();
+            blocking(move || {
+                let previous = previous_prime(&n, rounds, &budget);
+                (previous, n.next_prime_ref().complete())
+            })
+            .await
+        };
+        let text = match previous {
+            Some(previous) => {
+                format!("The nearest primes are (#{previous}) below it and (#{next}) above it.")
+            }
+            None => {
+                complete = *n <= 2;
+                format!("The next prime is (#{next}).")
+            }
+        };
+        tx.send(Fact::basic(Category::Primality, 0, text)).await?;
+    }
+
+    let count = match n.to_u64() {
+        Some(m) if m >= 2 => {
+            let budget = ctx.budget().clone();
+            blocking(move || prime_pi(m, &budget)).await
+        }
+        _ => None,
+    };
+    let text = match count {
+        Some(k) if ctx.primality().await != IsPrime::No => {
+            format!("Is the (#{k}){} prime.", ordinal_suffix(k))
+        }
+        Some(k) => format!("There are (#{k}) primes up to it."),
+        None => {
+            // Counting them would have been possible with more time.
+            complete &= *n < 2 || *n > EXACT_MAX;
+            match estimate_prime_pi(n) {
+                Some(estimate) => describe_estimate(estimate),
+                None if complete => return Ok(()),
+                None => return Err(Stopped::OutOfTime),
+            }
+        }
+    };
+    tx.send(Fact::basic(Category::Primality, 0, text)).await?;
+    if !complete {
+        return Err(Stopped::OutOfTime);
+    }
+    Ok(())
+}
+
+pub struct NearestPrimes;
+
+impl Nerd for NearestPrimes {
+    fn name(&self) -> &'static str {
+        "nearest-primes"
+    }
+
+    fn description(&self) -> &'static str {
+        "The primes just below and above the number, and how many primes there are up to it."
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(nearest_primes(ctx, tx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+    use rug::ops::Pow;
+
+    async fn texts(n: Integer) -> Vec<String> {
+        let (tx, mut rx) = mpsc::channel(2);
+        nearest_primes(Arc::new(NumberContext::new(n)), tx)
+            .await
+            .unwrap();
+        let mut texts = Vec::new();
+        while let Some(fact) = rx.recv().await {
+            if let Fact::Basic { text, .. } = fact {
+                texts.push(text);
+            }
+        }
+        texts
+    }
+
+    #[test]
+    fn describes_small_numbers() {
+        crate::test_harness!(|| {
+            assert_eq!(texts(Integer::from(0)).await, ["The next prime is (#2)."]);
+            assert_eq!(
+                texts(Integer::from(2)).await,
+                ["The next prime is (#3).", "Is the (#1)st prime."]
+            );
+            assert_eq!(
+                texts(Integer::from(37)).await,
+                [
+                    "The nearest primes are (#31) below it and (#41) above it.",
+                    "Is the (#12)th prime."
+                ]
+            );
+            assert_eq!(
+                texts(Integer::from(100)).await,
+                [
+                    "The nearest primes are (#97) below it and (#101) above it.",
+                    "There are (#25) primes up to it."
+                ]
+            );
+        });
+    }
+
+    #[test]
+    fn estimates_large_counts() {
+        crate::test_harness!(|| {
+            let texts = texts(Integer::from(10).pow(24)).await;
+            assert_eq!(
+                texts[1],
+                "There are about 1.843×10(^22) primes up to it, between 1.842×10(^22) and 1.844×10(^22)."
+            );
+            // The bounds get relatively closer together, so they need more digits to tell apart.
+            let estimate = estimate_prime_pi(&Integer::from(10).pow(1000)).unwrap();
+            assert_eq!(
+                describe_estimate(estimate),
+                "There are about 4.344833×10(^996) primes up to it, between 4.344830×10(^996) and 4.344833×10(^996)."
+            );
+        });
+    }
+
+    #[test]
+    fn ordinals() {
+        let ordinals: Vec<_> = [1, 2, 3, 4, 11, 12, 13, 21, 22, 101, 111, 1003]
+            .into_iter()
+            .map(|k| format!("{k}{}", ordinal_suffix(k)))
+            .collect();
+        assert_eq!(
+            ordinals,
+            [
+                "1st", "2nd", "3rd", "4th", "11th", "12th", "13th", "21st", "22nd", "101st",
+                "111th", "1003rd"
+            ]
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn previous_prime_is_prime(n in 3..1_000_000u32) {
+            let p = previous_prime(&Integer::from(n), 30, &Budget::unlimited()).unwrap();
+            prop_assert!(p < n);
+            prop_assert!(p.is_probably_prime(30) != IsPrime::No);
+            prop_assert!(p.next_prime_ref().complete() >= n);
+        }
+    }
+}
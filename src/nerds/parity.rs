@@ -1,18 +1,39 @@
 use std::sync::Arc;
 
-use rug::Integer;
 use tokio::sync::mpsc;
 
-use super::Fact;
+use super::{Category, Class, Fact, Nerd, NerdFuture, NerdResult, NumberContext};
 
-pub async fn parity(n: Arc<Integer>, tx: mpsc::Sender<Fact>) {
-    tx.send(Fact::Basic(if n.is_even() {
-        "Is an even number.".to_string()
+pub async fn parity(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let (fact, class) = if ctx.n().is_even() {
+        ("Is an even number.", Class::Even)
     } else {
-        "Is an odd number.".to_string()
-    }))
-    .await
-    .unwrap();
+        ("Is an odd number.", Class::Odd)
+    };
+    tx.send(Fact::basic(Category::Divisibility, 3, fact))
+        .await?;
+    tx.send(Fact::class(class)).await?;
+    Ok(())
+}
+
+pub struct Parity;
+
+impl Nerd for Parity {
+    fn name(&self) -> &'static str {
+        "parity"
+    }
+
+    fn description(&self) -> &'static str {
+        "Whether the number is even or odd."
+    }
+
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(parity(ctx, tx))
+    }
 }
 
 #[cfg(test)]
@@ -20,18 +41,19 @@ mod tests {
     use super::*;
 
     use proptest::prelude::*;
-    use rug::Complete;
+    use rug::{Complete, Integer};
 
     #[test]
     fn even() {
         crate::test_harness!(|(n in "[0-9]+")| {
             let x = Integer::parse(n).unwrap().complete() * 2;
-            let (tx, mut rx) = mpsc::channel(1);
-            parity(Arc::new(x), tx).await;
+            let (tx, mut rx) = mpsc::channel(2);
+            parity(Arc::new(NumberContext::new(x)), tx).await.unwrap();
             prop_assert_eq!(
                 rx.recv().await,
-                Some(Fact::Basic("Is an even number.".to_string()))
-            )
+                Some(Fact::basic(Category::Divisibility, 3, "Is an even number."))
+            );
+            prop_assert_eq!(rx.recv().await, Some(Fact::class(Class::Even)))
         });
     }
 
@@ -39,12 +61,13 @@ mod tests {
     fn odd() {
         crate::test_harness!(|(n in "[0-9]+")| {
             let x = Integer::parse(n).unwrap().complete() * 2 + 1;
-            let (tx, mut rx) = mpsc::channel(1);
-            parity(Arc::new(x), tx).await;
+            let (tx, mut rx) = mpsc::channel(2);
+            parity(Arc::new(NumberContext::new(x)), tx).await.unwrap();
             prop_assert_eq!(
                 rx.recv().await,
-                Some(Fact::Basic("Is an odd number.".to_string()))
-            )
+                Some(Fact::basic(Category::Divisibility, 3, "Is an odd number."))
+            );
+            prop_assert_eq!(rx.recv().await, Some(Fact::class(Class::Odd)))
         });
     }
 }
@@ -1,132 +1,123 @@
 /// This module finds numbers of the form x^y for x,y >= 2 integer.
-/// For any N = x^y, `y` is upper bounded by ln(N)/ln(x) <= ln(N)/ln(2) <= log_2(N),
-/// so finding `y` can be done by simply looping through all numbers 2 <= i <= log_2(N).
-/// and testing if the `y`th root of `N` is an integer.
 ///
-/// This is quite slow, so we do have optimizations.
+/// N = x^y exactly when `y` divides the multiplicity of every prime factor of N,
+/// so once N is factored the largest possible `y` is simply the gcd of those multiplicities.
 ///
-/// If a prime factor of the form p^k divides N, then we can test for a valid `y` simply by testing
-/// if `y` divides `k` and (N/p^k)^(1/y) is an integer.
-/// We can therefore speed this up significantly for most composite numbers by testing against
-/// a pre-programmed list of prime numbers.
+/// Big numbers are not always factored completely, in which case `y` still has to divide
+/// the multiplicities of the prime factors we did find, and we test each such `y` by checking
+/// if the `y`th root of N is an integer.
+///
+/// If no prime factors were found at all, we fall back to the slow way: For any N = x^y,
+/// `y` is upper bounded by ln(N)/ln(x) <= ln(N)/ln(2) <= log_2(N), so we loop through
+/// all numbers 2 <= y <= log_2(N).
 use std::sync::Arc;
 
-use num_traits::identities::One;
 use rug::{Complete, Integer};
 use tokio::sync::mpsc;
 
-use super::Fact;
+use super::{
+    blocking, Class, Fact, Nerd, NerdFuture, NerdResult, NumberContext, Stopped, PRIMALITY_MAX_BITS,
+};
 
-const SMALL_PRIMES: &[u32] = &[
-    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53, 59, 61, 67, 71, 73, 79, 83, 89, 97,
-];
+fn gcd(mut a: u32, mut b: u32) -> u32 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
 
 /// Returns (x,y) such that x^y = N, x > 1, y > 1 if such a pair exists.
 /// Because y <= log2(N), and N fits in less than 4GB because otherwise our server would crash,
 /// `y` fits in a u32.
 /// Expects n > 1.
-fn power_form_impl(mut n: Integer) -> Option<(Integer, u32)> {
-    let mut rem = Integer::new();
-
-    // First, test: Does N have a prime factor of the form p^k?
-    let mut prime_factor: Option<(u32, u32)> = None;
-    for &prime in SMALL_PRIMES.iter() {
-        if n.is_divisible_u(prime) {
-            // By how much?
-            n.div_exact_u_mut(prime);
-            let mut k = 1;
-            while n.is_divisible_u(prime) {
-                n.div_exact_u_mut(prime);
-                k += 1;
-            }
-            prime_factor = Some((prime, k));
-            break;
-        }
-    }
+async fn power_form_impl(ctx: &NumberContext) -> Option<(Integer, u32)> {
+    let n = ctx.n();
+    let factorization = ctx.factorization().await;
+    let exponent_gcd = factorization.primes.iter().map(|&(_, k)| k).reduce(gcd);
 
-    macro_rules! check_integer_root {
-        ($n:ident, $y:expr) => {
-            let y = $y;
-            let mut x = $n.clone();
-            x.root_rem_mut(&mut rem, y);
-            if rem.is_zero() {
-                if let Some((p, k)) = prime_factor {
-                    // We reconstruct the real `x` by
-                    // computing x * p^(k/y).
-                    x *= Integer::i_pow_u(p as i32, k / y).complete();
-                }
-                return Some((x, y));
-            }
-        };
+    let (max_y, must_divide) = match (exponent_gcd, &factorization.cofactor) {
+        (Some(g), None) => return (g > 1).then(|| (n.root_ref(g).complete(), g)),
+        (Some(g), Some(_)) => (g, Some(g)),
+        (None, _) => (n.significant_bits() - 1, None),
+    };
+
+    // We do it in reverse so we can find the highest exponents first,
+    // since it's preferable to output 2^32 instead of 65536^2.
+    let shared_n = ctx.shared_n();
+    let budget = ctx.budget().clone();
+    let higher_power = blocking(move || {
+        (3..=max_y)
+            .rev()
+            .filter(|y| must_divide.is_none_or(|g| g % y == 0))
+            .take_while(|_| !budget.is_exhausted())
+            .find_map(|y| {
+                let (x, rem) = shared_n.root_rem_ref(y).complete();
+                rem.is_zero().then_some((x, y))
+            })
+    })
+    .await;
+    if higher_power.is_some() {
+        return higher_power;
     }
 
-    match prime_factor {
-        None => {
-            // We have no choice, do it the slow way.
-            let log2_n_floored = n.significant_bits();
-            // We do it in reverse so we can find the highest exponents first,
-            // since it's preferable to output 2^32 instead of 65536^2.
-            for y in (2..log2_n_floored).rev() {
-                check_integer_root!(n, y);
-            }
-            None
+    if max_y >= 2 && must_divide.is_none_or(|g| g % 2 == 0) {
+        // Other nerds are likely to want the square root as well.
+        let (x, rem) = ctx.sqrt_rem().await;
+        if rem.is_zero() {
+            return Some((x.clone(), 2));
         }
-        Some((_, k)) => {
-            // Note that in this branch,
-            // `n` is actually just the original N divided by p^k.
-            // The test still works because
-            // (N/p^k)^(1/y) = (N^(1/y)) / (p^(k/y))
-            // so N^(1/y) is guaranteed to be an integer if k%y==0 and (N/p^k)^(1/y) is an integer.
-
-            // First get rid of the small cases.
-            if k == 1 {
-                // Definitely can't be a power.
-                return None;
-            }
-            check_integer_root!(n, k);
-            if k == 2 {
-                // Can only be a square.
-                // We've already checked that above, so no other options.
-                return None;
-            }
+    }
+    None
+}
 
-            // We step through divisors 2-by-2 (skipping over evens) if `k` is odd,
-            // or 1-by-1 if `k` is even.
-            let step = 1 + k % 2;
-            // Start at 3 if k is odd, 2 if k is even.
-            let mut y_quot = 2 + k % 2;
-            // First test the higher powers.
-            while y_quot * y_quot <= k {
-                if k % y_quot == 0 {
-                    check_integer_root!(n, k / y_quot);
-                }
-                y_quot += step;
-            }
-            // Then test the lower powers.
-            y_quot = 2 + k % 2;
-            while y_quot * y_quot <= k {
-                if k % y_quot == 0 {
-                    check_integer_root!(n, y_quot);
-                }
-                y_quot += step;
-            }
-            // No candidates remaining.
-            None
+/// Expects n > 1.
+pub async fn power_form(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let Some((x, y)) = power_form_impl(&ctx).await else {
+        // Not every exponent may have been tried.
+        if ctx.budget().is_exhausted() {
+            return Err(Stopped::OutOfTime);
         }
+        return Ok(());
+    };
+    tx.send(Fact::form(
+        3,
+        "Perfect power form",
+        format!("(#{x})(^(#{y}))"),
+    ))
+    .await?;
+    tx.send(Fact::class(Class::PerfectPower)).await?;
+    // y is the largest possible exponent, so any other exponent divides it.
+    if y % 2 == 0 {
+        tx.send(Fact::class(Class::Square)).await?;
     }
+    Ok(())
 }
 
-pub async fn power_form(n: Arc<Integer>, tx: mpsc::Sender<Fact>) {
-    if n.is_zero() || n.as_ref().is_one() {
-        return;
+pub struct PowerForm;
+
+impl Nerd for PowerForm {
+    fn name(&self) -> &'static str {
+        "power-form"
     }
-    if let Some((x, y)) = power_form_impl(Arc::unwrap_or_clone(n)) {
-        tx.send(Fact::Form(
-            "Perfect power form".to_owned(),
-            format!("(#{x})(^(#{y}))"),
-        ))
-        .await
-        .unwrap();
+
+    fn description(&self) -> &'static str {
+        "Whether the number is a perfect power."
+    }
+
+    fn applies_to(&self, ctx: &NumberContext) -> bool {
+        *ctx.n() > 1
+    }
+
+    fn max_bits(&self) -> Option<u32> {
+        Some(PRIMALITY_MAX_BITS)
+    }
+
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(power_form(ctx, tx))
     }
 }
 
@@ -134,47 +125,56 @@ pub async fn power_form(n: Arc<Integer>, tx: mpsc::Sender<Fact>) {
 mod tests {
     use super::*;
 
+    use std::time::Duration;
+
     use proptest::prelude::*;
     use rug::ops::Pow;
 
     #[test]
     fn simpler_tests() {
-        macro_rules! check {
-            ($a:expr, ($b1:expr,$b2:expr)) => {
-                assert_eq!(power_form_impl($a.into()), Some(($b1.into(), $b2)))
-            };
-        }
-        check!(65536, (2, 16));
-        check!(2147483648u32, (2, 31));
-        check!(25, (5, 2));
-        check!(625, (5, 4));
-        check!(3125, (5, 5));
-        check!(
-            Integer::parse("1000000028000000294000001372000002401").unwrap(),
-            (1_000_000_007, 4)
-        );
+        crate::test_harness!(|| {
+            macro_rules! check {
+                ($a:expr, ($b1:expr,$b2:expr)) => {
+                    let ctx = NumberContext::new(Integer::from($a));
+                    assert_eq!(power_form_impl(&ctx).await, Some(($b1.into(), $b2)))
+                };
+            }
+            check!(65536, (2, 16));
+            check!(2147483648u32, (2, 31));
+            check!(25, (5, 2));
+            check!(625, (5, 4));
+            check!(3125, (5, 5));
+            check!(
+                Integer::parse("1000000028000000294000001372000002401").unwrap(),
+                (1_000_000_007, 4)
+            );
+        });
     }
 
-    proptest! {
-        #[test]
-        // exclude a=1
-        fn reconstruct_powers_large_base(a in "[1-9][0-9]+|[2-9]", b in 2..256u32) {
+    #[test]
+    // exclude a=1
+    fn reconstruct_powers_large_base() {
+        crate::test_harness!(|(a in "[1-9][0-9]+|[2-9]", b in 2..256u32)| {
             let a = Integer::parse(a).unwrap().complete();
             let n = a.pow(b);
-            let form = power_form_impl(n.clone());
+            // Some bases are hard to factor, so make sure the partial factorization is exercised too.
+            let ctx = NumberContext::new(n.clone()).with_factor_budget(Duration::from_millis(20));
+            let form = power_form_impl(&ctx).await;
             prop_assert!(form.is_some(), "couldn't find form for {n}");
             let Some((x, y)) = form else { unreachable!() };
             prop_assert_eq!(x.clone().pow(y), n.clone(), "failed equal: {}^{} != {}", x, y, n);
-        }
+        });
+    }
 
-        #[test]
-        fn reconstruct_powers_small_base(a in 2..256u32, b in 2..1024u32) {
+    #[test]
+    fn reconstruct_powers_small_base() {
+        crate::test_harness!(|(a in 2..256u32, b in 2..1024u32)| {
             let a = Integer::from(a);
             let n = a.pow(b);
-            let form = power_form_impl(n.clone());
+            let form = power_form_impl(&NumberContext::new(n.clone())).await;
             prop_assert!(form.is_some(), "couldn't find form for {n}");
             let Some((x, y)) = form else { unreachable!() };
             prop_assert_eq!(x.clone().pow(y), n.clone(), "failed equal: {}^{} != {}", x, y, n);
-        }
+        });
     }
 }
@@ -1,32 +1,55 @@
 use std::sync::Arc;
 
-use rug::{integer::IsPrime, Complete, Integer};
+use rug::integer::IsPrime;
 use tokio::sync::mpsc;
 
-use super::Fact;
+use super::{
+    Category, Class, Fact, Nerd, NerdFuture, NerdResult, NumberContext, PRIMALITY_MAX_BITS,
+};
 
-pub async fn prime(n: Arc<Integer>, tx: mpsc::Sender<Fact>) {
-    match n.is_probably_prime(30) {
-        IsPrime::Yes => tx
-            .send(Fact::Basic("Is a prime number.".to_string()))
-            .await
-            .unwrap(),
-        IsPrime::Probably => tx
-            .send(Fact::Basic(
-                "Is almost certainly a prime number.".to_string(),
-            ))
-            .await
-            .unwrap(),
-        IsPrime::No => return,
+pub async fn prime(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let fact = match ctx.primality().await {
+        IsPrime::Yes => "Is a prime number.",
+        IsPrime::Probably => "Is almost certainly a prime number.",
+        IsPrime::No => return Ok(()),
+    };
+    tx.send(Fact::basic(Category::Primality, 2, fact)).await?;
+    tx.send(Fact::class(Class::Prime)).await?;
+
+    let power = ctx.bits();
+    if ctx.n().count_ones() == Some(power) {
+        tx.send(Fact::basic(
+            Category::Primality,
+            1,
+            format!("Is a Mersenne prime: (#2)(^(#{power}))-(#1)"),
+        ))
+        .await?;
+        tx.send(Fact::class(Class::MersennePrime)).await?;
+    }
+    Ok(())
+}
+
+pub struct Prime;
+
+impl Nerd for Prime {
+    fn name(&self) -> &'static str {
+        "prime"
+    }
+
+    fn description(&self) -> &'static str {
+        "Whether the number is prime, and whether it is a Mersenne prime."
+    }
+
+    fn max_bits(&self) -> Option<u32> {
+        Some(PRIMALITY_MAX_BITS)
     }
 
-    if (&*n + 1_u8).complete().is_power_of_two() {
-        let power = n.count_ones().unwrap();
-        tx.send(Fact::Basic(format!(
-            "Is a Mersenne prime: (#2)(^(#{power}))-(#1)"
-        )))
-        .await
-        .unwrap();
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(prime(ctx, tx))
     }
 }
 
@@ -35,6 +58,7 @@ mod tests {
     use super::*;
 
     use proptest::prelude::*;
+    use rug::{Complete, Integer};
 
     #[test]
     fn no_composites() {
@@ -44,7 +68,7 @@ mod tests {
             let x = a*b;
 
             let (tx, mut rx) = mpsc::channel(2);
-            prime(Arc::new(x), tx).await;
+            prime(Arc::new(NumberContext::new(x)), tx).await.unwrap();
             prop_assert_eq!(
                 rx.recv().await,
                 None
@@ -55,18 +79,23 @@ mod tests {
     #[test]
     fn mersenne() {
         crate::test_harness!(|| {
-            let (tx, mut rx) = mpsc::channel(2);
+            let (tx, mut rx) = mpsc::channel(4);
             macro_rules! check {
                 ($a:expr, $b:expr) => {
-                    prime(Arc::new(Integer::from($a)), tx.clone()).await;
+                    prime(Arc::new(NumberContext::new(Integer::from($a))), tx.clone())
+                        .await
+                        .unwrap();
                     assert!(rx.recv().await.is_some());
+                    assert_eq!(rx.recv().await, Some(Fact::class(Class::Prime)));
                     assert_eq!(
                         rx.recv().await,
-                        Some(Fact::Basic(format!(
-                            "Is a Mersenne prime: (#2)(^(#{}))-(#1)",
-                            $b
-                        )))
+                        Some(Fact::basic(
+                            Category::Primality,
+                            1,
+                            format!("Is a Mersenne prime: (#2)(^(#{}))-(#1)", $b)
+                        ))
                     );
+                    assert_eq!(rx.recv().await, Some(Fact::class(Class::MersennePrime)));
                 };
             }
             check!(3, 2);
@@ -0,0 +1,351 @@
+//! Kinds of primes, defined by how they relate to other primes or by their form.
+use std::{sync::Arc, time::Duration};
+
+use rug::{integer::IsPrime, Complete, Integer};
+use tokio::sync::mpsc;
+
+use super::{
+    blocking, Category, Class, Fact, Nerd, NerdFuture, NerdResult, NumberContext, Stopped,
+};
+use crate::math::{
+    factorize::{factorize, is_prime},
+    Budget,
+};
+
+/// Each kind takes another primality test or two, which add up to too long for larger primes.
+const MAX_BITS: u32 = 2048;
+/// Whether p + 2 is the product of two primes is only worth finding out up to this size.
+const CHEN_MAX_BITS: u32 = 256;
+const CHEN_FACTOR_BUDGET: Duration = Duration::from_millis(100);
+
+fn link_all(numbers: &[Integer], separator: &str) -> String {
+    let links: Vec<_> = numbers.iter().map(|m| format!("(#{m})")).collect();
+    links.join(separator)
+}
+
+/// The Cunningham chain through `p`, in which each prime is twice the one before plus `step`,
+/// which is 1 for chains of the first kind and -1 for the second kind.
+fn cunningham_chain(p: &Integer, step: i32, rounds: u32) -> Vec<Integer> {
+    let mut chain = vec![p.clone()];
+    loop {
+        let before = (&chain[0] - step).complete();
+        if before.is_odd() {
+            break;
+        }
+        let before = before / 2_u8;
+        if !is_prime(&before, rounds) {
+            break;
+        }
+        chain.insert(0, before);
+    }
+    loop {
+        // Note: This can't fail, the chain starts with `p`.
+        let after = (chain.last().unwrap() * 2_u8).complete() + step;
+        if !is_prime(&after, rounds) {
+            break;
+        }
+        chain.push(after);
+    }
+    chain
+}
+
+/// Splits `m` > 0 into k×2^e with k odd.
+fn odd_part(m: &Integer) -> (Integer, u32) {
+    // Note: This can't fail, m has a one bit.
+    let e = m.find_one(0).unwrap();
+    ((m >> e).complete(), e)
+}
+
+/// Everything there is to say about the prime `p`, stopping early once the budget runs out,
+/// and whether there was time to look at every kind.
+fn kinds(p: &Integer, rounds: u32, budget: &Budget) -> (Vec<Fact>, bool) {
+    let mut facts = Vec::new();
+    let mut say = |priority: u8, text: String, classes: &[Class]| {
+        facts.push(Fact::basic(Category::Primality, priority, text));
+        facts.extend(classes.iter().copied().map(Fact::class));
+    };
+
+    for (gap, name, class) in [
+        (2_u8, "twin", Class::TwinPrime),
+        (4, "cousin", Class::CousinPrime),
+        (6, "sexy", Class::SexyPrime),
+    ] {
+        let partners: Vec<_> = [(p - gap).complete(), (p + gap).complete()]
+            .into_iter()
+            .filter(|m| is_prime(m, rounds))
+            .collect();
+        if !partners.is_empty() {
+            say(
+                1,
+                format!(
+                    "Is a {name} prime, (#{gap}) apart from {}.",
+                    link_all(&partners, " and ")
+                ),
+                &[class],
+            );
+        }
+    }
+    if budget.is_exhausted() {
+        return (facts, false);
+    }
+
+    let doubled = (p * 2_u8).complete() + 1_u8;
+    if is_prime(&doubled, rounds) {
+        say(
+            1,
+            format!("Is a Sophie Germain prime: twice it plus one, (#{doubled}), is also prime."),
+            &[Class::SophieGermainPrime],
+        );
+    }
+    let halved = (p - 1_u8).complete() / 2_u8;
+    if p.is_odd() && is_prime(&halved, rounds) {
+        say(
+            1,
+            format!("Is a safe prime: half of one less than it, (#{halved}), is also prime."),
+            &[Class::SafePrime],
+        );
+    }
+    for (step, kind, change) in [(1, "first", "plus"), (-1, "second", "minus")] {
+        // Chains of two are already covered by Sophie Germain and safe primes.
+        let chain = cunningham_chain(p, step, rounds);
+        if chain.len() >= 3 {
+            say(
+                0,
+                format!(
+                    "Is in a Cunningham chain of the {kind} kind of (#{}) primes, each twice the one before {change} one: {}.",
+                    chain.len(),
+                    link_all(&chain, " → ")
+                ),
+                &[],
+            );
+        }
+    }
+    if budget.is_exhausted() {
+        return (facts, false);
+    }
+
+    let mut complete = true;
+    let next = (p + 2_u8).complete();
+    if is_prime(&next, rounds) {
+        say(
+            1,
+            format!("Is a Chen prime: two more than it, (#{next}), is also prime."),
+            &[Class::ChenPrime],
+        );
+    } else if p.significant_bits() <= CHEN_MAX_BITS {
+        let factorization = factorize(&next, rounds, &budget.limited_to(CHEN_FACTOR_BUDGET));
+        complete = factorization.cofactor.is_none();
+        let semiprime = match factorization.primes.as_slice() {
+            _ if !complete => None,
+            [(a, 1), (b, 1)] => Some((a, b)),
+            [(a, 2)] => Some((a, a)),
+            _ => None,
+        };
+        if let Some((a, b)) = semiprime {
+            say(
+                1,
+                format!(
+                    "Is a Chen prime: two more than it, (#{next}) = (#{a})×(#{b}), is the product of two primes."
+                ),
+                &[Class::ChenPrime],
+            );
+        }
+    }
+
+    let digits = p.to_string();
+    if digits.len() > 1 {
+        let reversed: String = digits.chars().rev().collect();
+        if reversed == digits {
+            say(
+                1,
+                "Is a palindromic prime.".to_owned(),
+                &[Class::PalindromicPrime],
+            );
+        } else {
+            // Note: This can't fail, the digits were just formatted.
+            let reversed = Integer::from_str_radix(&reversed, 10).unwrap();
+            if is_prime(&reversed, rounds) {
+                say(
+                    1,
+                    format!("Is an emirp: its digits reversed make another prime, (#{reversed})."),
+                    &[Class::Emirp],
+                );
+            }
+        }
+    }
+
+    let below = (p - 1_u8).complete();
+    let above = (p + 1_u8).complete();
+    let (k, e) = odd_part(&below);
+    if k == 1 && e.is_power_of_two() {
+        say(
+            1,
+            format!("Is a Fermat prime: (#2)(^(#{e}))+(#1), where the exponent is a power of two."),
+            // Fermat primes are also the Proth primes with k = 1.
+            &[Class::FermatPrime, Class::ProthPrime],
+        );
+    } else if k > 1 && k.significant_bits() <= e {
+        say(
+            0,
+            format!("Is a Proth prime: (#{k})×(#2)(^(#{e}))+(#1), where (#{k}) < (#2)(^(#{e}))."),
+            &[Class::ProthPrime],
+        );
+    }
+    // k = 1 makes a Mersenne prime, which the prime nerd points out.
+    let (k, e) = odd_part(&above);
+    if k > 1 && k.significant_bits() <= e {
+        say(
+            0,
+            format!(
+                "Is a prime of the form (#{k})×(#2)(^(#{e}))-(#1), where (#{k}) < (#2)(^(#{e}))."
+            ),
+            &[],
+        );
+    }
+    let (k, q) = odd_part(&((p * 3_u8).complete() - 1_u8));
+    if k == 1 && q > 2 && is_prime(&Integer::from(q), rounds) {
+        say(
+            1,
+            format!("Is a Wagstaff prime: ((#2)(^(#{q}))+(#1))/(#3)."),
+            &[Class::WagstaffPrime],
+        );
+    }
+    (facts, complete)
+}
+
+/// Expects n > 1.
+pub async fn prime_kinds(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    if ctx.primality().await == IsPrime::No {
+        return Ok(());
+    }
+    let (facts, complete) = {
+        let p = ctx.shared_n();
+        let rounds = ctx.prime_rounds();
+        let budget = ctx.budget().clone();
+        blocking(move || kinds(&p, rounds, &budget)).await
+    };
+    for fact in facts {
+        tx.send(fact).await?;
+    }
+    if !complete {
+        return Err(Stopped::OutOfTime);
+    }
+    Ok(())
+}
+
+pub struct PrimeKinds;
+
+impl Nerd for PrimeKinds {
+    fn name(&self) -> &'static str {
+        "prime-kinds"
+    }
+
+    fn description(&self) -> &'static str {
+        "Which kinds of prime a prime is, like twin, Sophie Germain, safe, Chen, emirp, Fermat or Wagstaff primes."
+    }
+
+    fn applies_to(&self, ctx: &NumberContext) -> bool {
+        *ctx.n() > 1
+    }
+
+    fn max_bits(&self) -> Option<u32> {
+        Some(MAX_BITS)
+    }
+
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(prime_kinds(ctx, tx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn classes(p: u32) -> Vec<Class> {
+        kinds(&Integer::from(p), 30, &Budget::unlimited())
+            .0
+            .into_iter()
+            .filter_map(|fact| match fact {
+                Fact::Class { class } => Some(class),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn texts(p: u32) -> Vec<String> {
+        kinds(&Integer::from(p), 30, &Budget::unlimited())
+            .0
+            .into_iter()
+            .filter_map(|fact| match fact {
+                Fact::Basic { text, .. } => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn classifies() {
+        use Class::*;
+        assert_eq!(
+            classes(13),
+            [
+                TwinPrime,
+                CousinPrime,
+                SexyPrime,
+                ChenPrime,
+                Emirp,
+                ProthPrime
+            ]
+        );
+        assert_eq!(classes(97), [CousinPrime, SexyPrime, Emirp, ProthPrime]);
+        assert_eq!(
+            classes(5),
+            [
+                TwinPrime,
+                SexyPrime,
+                SophieGermainPrime,
+                SafePrime,
+                ChenPrime,
+                FermatPrime,
+                ProthPrime
+            ]
+        );
+        assert!(classes(101).contains(&PalindromicPrime));
+        assert!(classes(65537).contains(&FermatPrime));
+        assert!(classes(43).contains(&WagstaffPrime));
+        assert!(classes(3).contains(&FermatPrime));
+        assert!(!classes(7).contains(&FermatPrime));
+        // 47 + 2 = 7².
+        assert!(classes(47).contains(&ChenPrime));
+        // 43 + 2 = 3²×5.
+        assert!(!classes(43).contains(&ChenPrime));
+    }
+
+    #[test]
+    fn stops_when_out_of_time() {
+        assert!(kinds(&Integer::from(13), 30, &Budget::unlimited()).1);
+        let budget = Budget::unlimited();
+        budget.cancel();
+        let (facts, complete) = kinds(&Integer::from(13), 30, &budget);
+        assert!(!complete);
+        // The twin primes and such are always looked for.
+        assert!(facts.contains(&Fact::class(Class::TwinPrime)));
+    }
+
+    #[test]
+    fn links_related_primes() {
+        let two = texts(2);
+        assert!(two.contains(&"Is in a Cunningham chain of the first kind of (#5) primes, each twice the one before plus one: (#2) → (#5) → (#11) → (#23) → (#47).".to_owned()));
+        assert!(two.contains(
+            &"Is in a Cunningham chain of the second kind of (#3) primes, each twice the one before minus one: (#2) → (#3) → (#5).".to_owned()
+        ));
+        assert!(texts(13).contains(&"Is a sexy prime, (#6) apart from (#7) and (#19).".to_owned()));
+        assert!(texts(23).contains(
+            &"Is a prime of the form (#3)×(#2)(^(#3))-(#1), where (#3) < (#2)(^(#3)).".to_owned()
+        ));
+    }
+}
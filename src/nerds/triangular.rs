@@ -1,21 +1,46 @@
 use std::sync::Arc;
 
-use rug::Integer;
+use rug::{Complete, Integer};
 use tokio::sync::mpsc;
 
-use super::Fact;
+use super::{blocking, Category, Class, Fact, Nerd, NerdFuture, NerdResult, NumberContext};
 
-pub async fn triangular(n: Arc<Integer>, tx: mpsc::Sender<Fact>) {
-    let (disc, rem) = (Arc::unwrap_or_clone(n) * 8_u8 + 1_u8).sqrt_rem(Integer::new());
+pub async fn triangular(ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdResult {
+    let n = ctx.shared_n();
+    let (disc, rem) =
+        blocking(move || ((&*n * 8_u8).complete() + 1_u8).sqrt_rem(Integer::new())).await;
     if !rem.is_zero() || disc.is_even() {
-        return;
+        return Ok(());
     }
     let root = (disc - 1) / 2;
-    tx.send(Fact::Basic(format!(
-        "Is the (#{root})th triangular number."
-    )))
-    .await
-    .unwrap();
+    tx.send(Fact::basic(
+        Category::Sequences,
+        1,
+        format!("Is the (#{root})th triangular number."),
+    ))
+    .await?;
+    tx.send(Fact::class(Class::Triangular)).await?;
+    Ok(())
+}
+
+pub struct Triangular;
+
+impl Nerd for Triangular {
+    fn name(&self) -> &'static str {
+        "triangular"
+    }
+
+    fn description(&self) -> &'static str {
+        "Whether the number is a triangular number, and which one."
+    }
+
+    fn version(&self) -> u32 {
+        2
+    }
+
+    fn run(&self, ctx: Arc<NumberContext>, tx: mpsc::Sender<Fact>) -> NerdFuture {
+        Box::pin(triangular(ctx, tx))
+    }
 }
 
 #[cfg(test)]
@@ -24,7 +49,6 @@ mod tests {
     use crate::test_harness;
 
     use proptest::prelude::*;
-    use rug::Complete;
 
     #[test]
     fn positive_match() {
@@ -32,12 +56,13 @@ mod tests {
             let nth = Integer::parse(n).unwrap().complete();
             let x = (&nth + &nth*&nth).complete()/2;
 
-            let (tx, mut rx) = mpsc::channel(1);
-            triangular(Arc::new(x), tx).await;
+            let (tx, mut rx) = mpsc::channel(2);
+            triangular(Arc::new(NumberContext::new(x)), tx).await.unwrap();
             prop_assert_eq!(
                 rx.recv().await,
-                Some(Fact::Basic(format!("Is the (#{nth})th triangular number.")))
+                Some(Fact::basic(Category::Sequences, 1, format!("Is the (#{nth})th triangular number.")))
             );
+            prop_assert_eq!(rx.recv().await, Some(Fact::class(Class::Triangular)));
         });
     }
 }
@@ -0,0 +1,142 @@
+//! Rendering the wiki as a static site, for hosting without running the server.
+//!
+//! Every number gets a directory containing an `index.html`,
+//! so that the pages can be linked to as `../k/` from each other.
+use std::{fmt::Write, io, ops::RangeInclusive, path::Path, sync::Arc, time::Instant};
+
+use askama::Template;
+use futures_util::{stream, StreamExt, TryStreamExt};
+use rug::Integer;
+
+use crate::{
+    assets::Assets, config::Config, filters::LinkStyle, manual::Library, nerds, store::Store,
+    IntTemplate,
+};
+
+#[derive(Template)]
+#[template(path = "site_index.html")]
+struct IndexTemplate {
+    range: RangeInclusive<u64>,
+}
+
+/// Writes a page for every number in `range` to `out`, along with an index page and the assets.
+/// If there is a `base_url`, it is prefixed to the pages' paths in a sitemap.
+pub async fn build(
+    config: &Config,
+    registry: &nerds::Registry,
+    store: Option<&Store>,
+    range: RangeInclusive<u64>,
+    out: &Path,
+    base_url: Option<&str>,
+) -> io::Result<()> {
+    let started = Instant::now();
+    tokio::fs::create_dir_all(out).await?;
+
+    // Most numbers are quick to analyse, so do several at a time.
+    let parallelism = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let links = LinkStyle::Static(range.clone());
+    let library = Library::load(config.manual_dir.as_deref(), &links)?;
+    stream::iter(range.clone())
+        .map(Ok)
+        .try_for_each_concurrent(parallelism, |n| {
+            write_page(config, registry, store, &library, &links, out, n)
+        })
+        .await?;
+
+    let index = IndexTemplate {
+        range: range.clone(),
+    };
+    tokio::fs::write(
+        out.join("index.html"),
+        index.render().map_err(io::Error::other)?,
+    )
+    .await?;
+    match base_url {
+        Some(base_url) => {
+            tokio::fs::write(out.join("sitemap.xml"), sitemap(&range, base_url)).await?;
+        }
+        None => eprintln!("Note: not writing a sitemap, which needs --base-url"),
+    }
+
+    let assets_dir = out.join("assets");
+    tokio::fs::create_dir_all(&assets_dir).await?;
+    let assets = Assets::new(config.assets_dir.clone());
+    for name in assets.names() {
+        if let Some(data) = assets.get(&name).await {
+            tokio::fs::write(assets_dir.join(&name), data).await?;
+        }
+    }
+
+    eprintln!(
+        "Wrote {} pages to {} in {:.1?}",
+        range.count(),
+        out.display(),
+        started.elapsed()
+    );
+    Ok(())
+}
+
+async fn write_page(
+    config: &Config,
+    registry: &nerds::Registry,
+    store: Option<&Store>,
+    library: &Library,
+    links: &LinkStyle,
+    out: &Path,
+    n: u64,
+) -> io::Result<()> {
+    let ctx = config.number_context(Integer::from(n));
+    let info = nerds::ask_nerds(registry, ctx.clone(), store).await;
+    let page = IntTemplate {
+        manual: library.number(ctx.n()),
+        class_manuals: library.classes_of(ctx.n(), &info.classes),
+        ctx,
+        info: Arc::new(info),
+        links: links.clone(),
+        redirected_from: None,
+        read_as: None,
+        pending: false,
+        seen: 0,
+    };
+
+    let dir = out.join(n.to_string());
+    tokio::fs::create_dir_all(&dir).await?;
+    tokio::fs::write(
+        dir.join("index.html"),
+        page.render().map_err(io::Error::other)?,
+    )
+    .await
+}
+
+fn sitemap(range: &RangeInclusive<u64>, base_url: &str) -> String {
+    let base_url = askama_escape::escape(base_url.trim_end_matches('/'), askama_escape::Html);
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    // Note: Writing to a String can't fail.
+    let _ = writeln!(out, "  <url><loc>{base_url}/</loc></url>");
+    for n in range.clone() {
+        let _ = writeln!(out, "  <url><loc>{base_url}/{n}/</loc></url>");
+    }
+    out.push_str("</urlset>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sitemap_lists_every_page() {
+        assert_eq!(
+            sitemap(&(1..=2), "https://example.com/wiki/"),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n  \
+             <url><loc>https://example.com/wiki/</loc></url>\n  \
+             <url><loc>https://example.com/wiki/1/</loc></url>\n  \
+             <url><loc>https://example.com/wiki/2/</loc></url>\n\
+             </urlset>\n"
+        );
+    }
+}
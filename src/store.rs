@@ -0,0 +1,278 @@
+//! Keeping what the nerds found on disk, so expensive facts survive a restart.
+//!
+//! Facts are stored per nerd and number, along with the version of the nerd which found them.
+//! When a nerd changes its version, only its own facts are found again.
+use std::{
+    collections::HashMap,
+    ops::RangeInclusive,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use futures_util::{stream, StreamExt};
+use rug::{Complete, Integer};
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::{
+    config::Config,
+    nerds::{self, Fact},
+};
+
+/// How often `precompute` saves how far it got.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// What a nerd found about a number: its name, its version, and its facts.
+pub type Findings = (&'static str, u32, Vec<Fact>);
+
+/// An SQLite database of facts, which several processes may use at once.
+#[derive(Clone)]
+pub struct Store {
+    conn: Arc<Mutex<Connection>>,
+    min_duration: Duration,
+}
+
+impl Store {
+    /// Opens the store at `path`, creating it if necessary.
+    pub fn open(path: impl AsRef<Path>) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path)?;
+        // Let the server and `precompute` share the store.
+        conn.busy_timeout(Duration::from_secs(5))?;
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            CREATE TABLE IF NOT EXISTS facts (
+                nerd TEXT NOT NULL,
+                number TEXT NOT NULL,
+                version INTEGER NOT NULL,
+                facts TEXT NOT NULL,
+                PRIMARY KEY (nerd, number)
+            );
+            CREATE TABLE IF NOT EXISTS progress (
+                task TEXT PRIMARY KEY,
+                done TEXT NOT NULL
+            );",
+        )?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            min_duration: Duration::ZERO,
+        })
+    }
+
+    /// Only stores what took a nerd at least `min_duration` to find,
+    /// so the store isn't filled up with facts which are cheap to find again.
+    pub fn with_min_duration(mut self, min_duration: Duration) -> Self {
+        self.min_duration = min_duration;
+        self
+    }
+
+    pub fn min_duration(&self) -> Duration {
+        self.min_duration
+    }
+
+    /// The stored facts about the number with decimal digits `digits`,
+    /// for those of `nerds` (names and versions) which found them in the same version.
+    pub fn load(
+        &self,
+        digits: &str,
+        nerds: &[(&'static str, u32)],
+    ) -> rusqlite::Result<HashMap<&'static str, Vec<Fact>>> {
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare_cached(
+            "SELECT facts FROM facts WHERE nerd = ? AND number = ? AND version = ?",
+        )?;
+        let mut found = HashMap::new();
+        for &(name, version) in nerds {
+            let facts: Option<String> = query
+                .query_row(params![name, digits, version], |row| row.get(0))
+                .optional()?;
+            // Facts which can't be read anymore are simply found again.
+            if let Some(facts) = facts.and_then(|facts| serde_json::from_str(&facts).ok()) {
+                found.insert(name, facts);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Stores what nerds found about the number with decimal digits `digits`,
+    /// replacing what older versions of them found.
+    pub fn save(&self, digits: &str, findings: &[Findings]) -> rusqlite::Result<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut insert = tx.prepare_cached(
+                "INSERT OR REPLACE INTO facts (nerd, number, version, facts) VALUES (?, ?, ?, ?)",
+            )?;
+            for (name, version, facts) in findings {
+                // Note: This can't fail, facts only contain strings and numbers.
+                let facts = serde_json::to_string(facts).unwrap();
+                insert.execute(params![name, digits, version, facts])?;
+            }
+        }
+        tx.commit()
+    }
+
+    /// The last number `task` got done with, if it was started before.
+    fn progress(&self, task: &str) -> rusqlite::Result<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT done FROM progress WHERE task = ?", [task], |row| {
+            row.get(0)
+        })
+        .optional()
+    }
+
+    fn set_progress(&self, task: &str, done: &str) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO progress (task, done) VALUES (?, ?)",
+            [task, done],
+        )?;
+        Ok(())
+    }
+}
+
+/// Asks the nerds about every number in `range`, and stores everything they find.
+///
+/// The nerds get the larger `precompute_*` budgets rather than those for requests.
+/// Progress is saved along the way, so running this again for the same range
+/// continues where the last run stopped. Facts which ran out of time aren't stored.
+pub async fn precompute(
+    config: &Config,
+    registry: &nerds::Registry,
+    store: &Store,
+    range: RangeInclusive<Integer>,
+) -> rusqlite::Result<()> {
+    let started = Instant::now();
+    let store = store.clone().with_min_duration(Duration::ZERO);
+    let task = format!("precompute {}..={}", range.start(), range.end());
+
+    let (start, end) = range.into_inner();
+    let start = match blocking(&store, {
+        let task = task.clone();
+        move |store| store.progress(&task)
+    })
+    .await?
+    .and_then(|done| done.parse::<Integer>().ok())
+    {
+        Some(done) if done >= end => {
+            eprintln!("Already done with {task}");
+            return Ok(());
+        }
+        Some(done) => {
+            eprintln!("Resuming {task} after {done}");
+            done + 1_u8
+        }
+        None => start,
+    };
+
+    let numbers = std::iter::successors(Some(start.clone()).filter(|n| *n <= end), |n| {
+        Some((n + 1_u8).complete()).filter(|next| *next <= end)
+    });
+    // Most numbers are quick to analyse, so do several at a time.
+    let parallelism = std::thread::available_parallelism().map_or(4, |n| n.get());
+    let mut done = stream::iter(numbers)
+        .map(|n| {
+            let store = &store;
+            async move {
+                let ctx = config.precompute_context(n.clone());
+                nerds::ask_nerds(registry, ctx, Some(store)).await;
+                n
+            }
+        })
+        // Numbers are finished in order, so everything up to the last one is done.
+        .buffered(parallelism);
+
+    let mut saved = Instant::now();
+    while let Some(n) = done.next().await {
+        if n == end || saved.elapsed() >= PROGRESS_INTERVAL {
+            let task = task.clone();
+            let done = n.to_string();
+            blocking(&store, move |store| store.set_progress(&task, &done)).await?;
+            eprintln!("Done with {n} of {start}..={end}");
+            saved = Instant::now();
+        }
+    }
+
+    eprintln!("Finished {task} in {:.1?}", started.elapsed());
+    Ok(())
+}
+
+/// Uses the store on the blocking thread pool, since SQLite may have to wait for the disk.
+pub async fn blocking<T: Send + 'static>(
+    store: &Store,
+    f: impl FnOnce(&Store) -> T + Send + 'static,
+) -> T {
+    let store = store.clone();
+    match tokio::task::spawn_blocking(move || f(&store)).await {
+        Ok(res) => res,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::nerds::{Category, Class};
+
+    fn memory_store() -> Store {
+        Store::open(":memory:").unwrap()
+    }
+
+    #[test]
+    fn versions_are_separate() {
+        let store = memory_store();
+        let facts = vec![Fact::basic(Category::Divisibility, 3, "Is an even number.")];
+        store
+            .save(
+                "12",
+                &[("parity", 1, facts.clone()), ("fibonacci", 1, vec![])],
+            )
+            .unwrap();
+
+        let found = store
+            .load("12", &[("parity", 1), ("fibonacci", 2), ("prime", 1)])
+            .unwrap();
+        assert_eq!(found, HashMap::from([("parity", facts)]));
+        assert!(store.load("13", &[("parity", 1)]).unwrap().is_empty());
+
+        // A new version replaces the old one.
+        store.save("12", &[("parity", 2, vec![])]).unwrap();
+        assert!(store.load("12", &[("parity", 1)]).unwrap().is_empty());
+        assert_eq!(
+            store.load("12", &[("parity", 2)]).unwrap(),
+            HashMap::from([("parity", vec![])])
+        );
+    }
+
+    #[test]
+    fn precompute_resumes() {
+        crate::test_harness!(|| {
+            let store = memory_store();
+            let registry = nerds::Registry::default();
+            store.set_progress("precompute 1..=20", "10").unwrap();
+            precompute(
+                &Config::default(),
+                &registry,
+                &store,
+                Integer::from(1)..=Integer::from(20),
+            )
+            .await
+            .unwrap();
+
+            assert_eq!(
+                store.progress("precompute 1..=20").unwrap().as_deref(),
+                Some("20")
+            );
+            let parity = [("parity", 2)];
+            assert!(store.load("10", &parity).unwrap().is_empty());
+            assert_eq!(
+                store.load("12", &parity).unwrap()["parity"],
+                vec![
+                    Fact::basic(Category::Divisibility, 3, "Is an even number."),
+                    Fact::class(Class::Even),
+                ]
+            );
+        });
+    }
+}